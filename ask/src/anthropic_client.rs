@@ -18,6 +18,7 @@ pub struct AnthropicQuery {
     pub stop_sequences: Option<Vec<String>>,
     pub stream: bool,
     pub system: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<ToolDefinition>,
     pub messages: Vec<AnthropicMessage>,
 }
@@ -116,12 +117,17 @@ pub struct AnthropicResponse {
 /// Convert an Anthropic response to the internal response representation.
 fn anthropic_to_internal(response: AnthropicResponse) -> anyhow::Result<LlmResponse> {
     let mut text_blocks = Vec::with_capacity(2);
+    let mut raw_text = String::new();
     let mut tool_invocations = Vec::new();
 
     for content_block in response.content {
         match content_block {
             AnthropicContentBlock::Text { text } => {
                 crate::response_parsing::parse_text(&text, &mut text_blocks);
+                if !raw_text.is_empty() {
+                    raw_text.push_str("\n\n");
+                }
+                raw_text.push_str(&text);
             }
             AnthropicContentBlock::ToolUse { id, name, input } => {
                 tool_invocations.push(ToolInvocation { id, name, input });
@@ -134,24 +140,17 @@ fn anthropic_to_internal(response: AnthropicResponse) -> anyhow::Result<LlmRespo
 
     Ok(LlmResponse {
         text: text_blocks,
+        raw_text,
         tool_invocations,
     })
 }
 
 impl crate::llm_client::LlmQuery for AnthropicQuery {
-    fn create_query(system_prompt: String) -> Self {
-        let mut tool_map = std::collections::HashMap::<String, _>::new();
-        let mut tool_definitions = vec![];
-        for tool in crate::tools::rust_tools::get_rust_tools() {
-            let definition = tool.get_definition();
-            tool_map.insert(definition.name.to_string(), tool);
-            tool_definitions.push(definition);
-        }
-
+    fn create_query(system_prompt: String, tools: &crate::tools::ToolRegistry) -> Self {
         AnthropicQuery {
             messages: Vec::with_capacity(1),
             system: Some(system_prompt),
-            tools: tool_definitions,
+            tools: tools.definitions().cloned().collect(),
             stream: false,
             ..Default::default()
         }
@@ -0,0 +1,447 @@
+//! Audit log of the tool invocations `ask` performs.
+//!
+//! Every tool invocation is appended to a JSONL file in the cache directory so there is a record of what `ask` did on a machine.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use argh::FromArgs;
+
+/// The maximum number of characters of a tool result to store in the audit log.
+const MAX_RESULT_CHARS: usize = 2000;
+
+/// The approval state of the tool invocation that is currently running.
+static CURRENT_APPROVAL: Mutex<Option<bool>> = Mutex::new(None);
+
+/// Record that the user approved or denied an action requested by the currently running tool.
+///
+/// If a tool prompts the user multiple times the invocation is only considered approved if every prompt was approved.
+pub fn record_approval(approved: bool) {
+    let mut current = CURRENT_APPROVAL.lock().unwrap();
+    *current = Some(current.unwrap_or(true) && approved);
+}
+
+/// Take the approval state recorded for the current tool invocation.
+fn take_approval() -> Option<bool> {
+    CURRENT_APPROVAL.lock().unwrap().take()
+}
+
+/// The outcome of a tool invocation.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditStatus {
+    /// The tool ran successfully.
+    Success,
+    /// The tool returned an error.
+    Error,
+    /// The tool's prerequisites weren't satisfied so it wasn't run.
+    Unavailable,
+    /// The tool policy didn't allow the invocation so it wasn't run.
+    Denied,
+}
+
+impl std::fmt::Display for AuditStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Success => write!(f, "success"),
+            Self::Error => write!(f, "error"),
+            Self::Unavailable => write!(f, "unavailable"),
+            Self::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+/// An entry in the audit log.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+pub struct AuditEntry {
+    /// When the invocation started as an RFC 3339 timestamp.
+    pub timestamp: String,
+    /// The ID of the `ask` session the invocation belongs to.
+    pub session_id: String,
+    /// The name of the tool.
+    pub tool: String,
+    /// The input the LLM sent to the tool.
+    pub input: serde_json::Value,
+    /// Whether the user approved the invocation, `None` if the tool didn't prompt the user.
+    pub approved: Option<bool>,
+    /// How long the invocation took in milliseconds.
+    pub duration_ms: u64,
+    /// The outcome of the invocation.
+    pub status: AuditStatus,
+    /// The result of the invocation, truncated to a reasonable length.
+    pub result: String,
+}
+
+/// An append-only audit log.
+pub struct AuditLog {
+    /// The path of the JSONL file.
+    path: PathBuf,
+    /// The ID of the current session.
+    session_id: String,
+}
+
+/// Get the path of the audit log.
+fn get_audit_log_path(config: &productivity_config::Config) -> PathBuf {
+    config.cache_location.join("audit.jsonl")
+}
+
+impl AuditLog {
+    /// Create an audit log for a new session.
+    pub fn new(config: &productivity_config::Config) -> Self {
+        Self {
+            path: get_audit_log_path(config),
+            session_id: format!(
+                "{}-{}",
+                chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+                std::process::id()
+            ),
+        }
+    }
+
+    /// Start timing a tool invocation, redacting secrets from its input.
+    pub fn start(
+        &self,
+        tool: &str,
+        input: &serde_json::Value,
+        redactor: &crate::redaction::Redactor,
+    ) -> PendingAuditEntry {
+        // Clear any approvals left over from a previous invocation
+        let _ = take_approval();
+        PendingAuditEntry {
+            timestamp: chrono::Utc::now(),
+            started: std::time::Instant::now(),
+            tool: tool.to_string(),
+            input: redactor.redact_json(input),
+        }
+    }
+
+    /// Append a finished invocation to the log.
+    pub fn finish(
+        &self,
+        pending: PendingAuditEntry,
+        status: AuditStatus,
+        result: &str,
+    ) -> anyhow::Result<()> {
+        let mut truncated_result: String = result.chars().take(MAX_RESULT_CHARS).collect();
+        if truncated_result.len() < result.len() {
+            truncated_result.push_str("...");
+        }
+
+        let entry = AuditEntry {
+            timestamp: pending.timestamp.to_rfc3339(),
+            session_id: self.session_id.clone(),
+            tool: pending.tool,
+            input: pending.input,
+            approved: take_approval(),
+            duration_ms: u64::try_from(pending.started.elapsed().as_millis()).unwrap_or(u64::MAX),
+            status,
+            result: truncated_result,
+        };
+
+        let mut line = serde_json::to_string(&entry).context("Serializing audit entry")?;
+        line.push('\n');
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+        // The log records what the tools read and ran, so only the user can read it
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&self.path)
+            .with_context(|| format!("Opening the audit log at {}", self.path.display()))?;
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("Writing to the audit log at {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// A tool invocation which has started but not finished.
+pub struct PendingAuditEntry {
+    timestamp: chrono::DateTime<chrono::Utc>,
+    started: std::time::Instant,
+    tool: String,
+    input: serde_json::Value,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "audit")]
+/// Browse the audit log of tool invocations.
+pub struct AuditCommand {
+    #[argh(option)]
+    /// only show invocations of this tool
+    tool: Option<String>,
+    #[argh(option)]
+    /// only show invocations from this session
+    session: Option<String>,
+    #[argh(option)]
+    /// only show invocations with this status (success, error, unavailable, or denied)
+    status: Option<String>,
+    #[argh(option)]
+    /// only show invocations on or after this UTC date (YYYY-MM-DD)
+    since: Option<String>,
+    #[argh(option, default = "50")]
+    /// the maximum number of entries to show, most recent last
+    limit: usize,
+    #[argh(switch)]
+    /// print the matching entries as JSONL
+    json: bool,
+}
+
+impl AuditCommand {
+    /// Check whether an entry matches the filters.
+    fn matches(&self, entry: &AuditEntry, since: Option<&str>) -> bool {
+        self.tool.as_ref().is_none_or(|tool| &entry.tool == tool)
+            && self
+                .session
+                .as_ref()
+                .is_none_or(|session| &entry.session_id == session)
+            && self
+                .status
+                .as_ref()
+                .is_none_or(|status| entry.status.to_string() == *status)
+            // RFC 3339 timestamps in the same timezone sort lexicographically
+            && since.is_none_or(|since| entry.timestamp.as_str() >= since)
+    }
+
+    /// Read the last `limit` matching entries of the audit log at `path` with their JSONL lines, `None` if the log
+    /// doesn't exist.
+    fn read_entries(
+        &self,
+        path: &std::path::Path,
+        since: Option<&str>,
+    ) -> anyhow::Result<Option<std::collections::VecDeque<(String, AuditEntry)>>> {
+        anyhow::ensure!(self.limit > 0, "--limit must be at least 1");
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Opening the audit log at {}", path.display()))
+            }
+        };
+
+        let mut entries = std::collections::VecDeque::with_capacity(self.limit);
+        for (line_number, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.context("Reading the audit log")?;
+            let entry: AuditEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!("Skipping malformed audit log line {}: {e}", line_number + 1);
+                    continue;
+                }
+            };
+            if !self.matches(&entry, since) {
+                continue;
+            }
+            if entries.len() == self.limit {
+                entries.pop_front();
+            }
+            entries.push_back((line, entry));
+        }
+        Ok(Some(entries))
+    }
+
+    /// Print the matching audit log entries.
+    pub fn run(&self, config: &productivity_config::Config) -> anyhow::Result<()> {
+        let since = self
+            .since
+            .as_ref()
+            .map(|since| {
+                chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                    .with_context(|| format!("Parsing {since} as a YYYY-MM-DD date"))
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+            })
+            .transpose()?;
+
+        let path = get_audit_log_path(config);
+        let Some(entries) = self.read_entries(&path, since.as_deref())? else {
+            eprintln!("The audit log at {} is empty", path.display());
+            return Ok(());
+        };
+
+        for (line, entry) in entries {
+            if self.json {
+                println!("{line}");
+                continue;
+            }
+
+            let status = match entry.status {
+                AuditStatus::Success => console::style(entry.status).green(),
+                AuditStatus::Error => console::style(entry.status).red(),
+                AuditStatus::Unavailable | AuditStatus::Denied => {
+                    console::style(entry.status).yellow()
+                }
+            };
+            let approved = match entry.approved {
+                Some(true) => "approved",
+                Some(false) => "denied",
+                None => "no prompt",
+            };
+            println!(
+                "{} {} {} {status} ({approved}, {}ms)",
+                console::style(&entry.timestamp).dim(),
+                console::style(&entry.session_id).dim(),
+                console::style(&entry.tool).bold(),
+                entry.duration_ms,
+            );
+            println!("  input: {}", entry.input);
+            let first_result_line = entry.result.lines().next().unwrap_or_default();
+            println!("  result: {first_result_line}");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create an audit log in a new directory for a test.
+    fn create_log(name: &str) -> AuditLog {
+        let cache_location =
+            std::env::temp_dir().join(format!("ask-audit-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_location);
+        std::fs::create_dir_all(&cache_location).unwrap();
+        AuditLog::new(&productivity_config::Config {
+            cache_location,
+            ..productivity_config::Config::default()
+        })
+    }
+
+    /// Create an audit command with no filters.
+    fn command() -> AuditCommand {
+        AuditCommand {
+            tool: None,
+            session: None,
+            status: None,
+            since: None,
+            limit: 50,
+            json: false,
+        }
+    }
+
+    #[test]
+    fn appends_jsonl_records() {
+        let log = create_log("records");
+        let redactor =
+            crate::redaction::Redactor::new(&productivity_config::RedactionConfig::default())
+                .unwrap();
+        let input = serde_json::json!({"path": "/src"});
+        let pending = log.start("read_files", &input, &redactor);
+        record_approval(true);
+        record_approval(false);
+        log.finish(
+            pending,
+            AuditStatus::Success,
+            &"x".repeat(MAX_RESULT_CHARS + 1),
+        )
+        .unwrap();
+        let pending = log.start(
+            "search_files",
+            &serde_json::json!({"pattern": "password=hunter2"}),
+            &redactor,
+        );
+        log.finish(pending, AuditStatus::Denied, "denied").unwrap();
+
+        let content = std::fs::read_to_string(&log.path).unwrap();
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(
+            &std::fs::metadata(&log.path).unwrap().permissions(),
+        );
+        std::fs::remove_dir_all(log.path.parent().unwrap()).unwrap();
+        let entries: Vec<AuditEntry> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tool, "read_files");
+        assert_eq!(entries[0].input, input);
+        assert_eq!(entries[0].session_id, log.session_id);
+        assert_eq!(entries[0].approved, Some(false));
+        assert_eq!(entries[0].status, AuditStatus::Success);
+        assert_eq!(
+            entries[0].result,
+            format!("{}...", "x".repeat(MAX_RESULT_CHARS))
+        );
+        assert!(chrono::DateTime::parse_from_rfc3339(&entries[0].timestamp).is_ok());
+        // Approvals don't leak into the next invocation
+        assert_eq!(entries[1].approved, None);
+        assert_eq!(entries[1].status, AuditStatus::Denied);
+        assert_eq!(entries[1].result, "denied");
+        assert_eq!(
+            entries[1].input,
+            serde_json::json!({"pattern": "password=[REDACTED credential]"})
+        );
+        #[cfg(unix)]
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(content.contains("\"status\":\"denied\""));
+    }
+
+    #[test]
+    fn filters_entries() {
+        let log = create_log("filters");
+        let lines = [
+            r#"{"timestamp":"2026-01-01T10:00:00+00:00","session_id":"one","tool":"read_files","input":{},"approved":null,"duration_ms":1,"status":"success","result":""}"#,
+            "not json",
+            r#"{"timestamp":"2026-02-01T10:00:00+00:00","session_id":"one","tool":"search_files","input":{},"approved":true,"duration_ms":1,"status":"error","result":""}"#,
+            r#"{"timestamp":"2026-03-01T10:00:00+00:00","session_id":"two","tool":"read_files","input":{},"approved":false,"duration_ms":1,"status":"denied","result":""}"#,
+        ];
+        std::fs::write(&log.path, lines.join("\n")).unwrap();
+
+        let read = |command: AuditCommand, since: Option<&str>| -> Vec<String> {
+            command
+                .read_entries(&log.path, since)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|(_, entry)| entry.timestamp[..7].to_string())
+                .collect()
+        };
+        assert_eq!(read(command(), None), ["2026-01", "2026-02", "2026-03"]);
+        assert_eq!(
+            read(
+                AuditCommand {
+                    tool: Some("read_files".to_string()),
+                    ..command()
+                },
+                None
+            ),
+            ["2026-01", "2026-03"]
+        );
+        assert_eq!(
+            read(
+                AuditCommand {
+                    session: Some("one".to_string()),
+                    status: Some("error".to_string()),
+                    ..command()
+                },
+                None
+            ),
+            ["2026-02"]
+        );
+        assert_eq!(read(command(), Some("2026-02-01")), ["2026-02", "2026-03"]);
+        assert_eq!(
+            read(
+                AuditCommand {
+                    limit: 1,
+                    ..command()
+                },
+                None
+            ),
+            ["2026-03"]
+        );
+        assert!(AuditCommand {
+            limit: 0,
+            ..command()
+        }
+        .read_entries(&log.path, None)
+        .is_err());
+        let entries = command().read_entries(&log.path, None).unwrap().unwrap();
+        assert_eq!(entries[0].0, lines[0]);
+
+        std::fs::remove_dir_all(log.path.parent().unwrap()).unwrap();
+        assert!(command().read_entries(&log.path, None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,317 @@
+//! Terminal color capability detection and syntax highlighting escapes.
+
+use std::fmt::Write;
+
+use anyhow::Context;
+
+/// The syntax highlighting theme used when none is configured.
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// The colors a terminal can display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// 24-bit RGB colors.
+    TrueColor,
+    /// The xterm 256 color palette.
+    Ansi256,
+    /// The 16 standard ANSI colors.
+    Ansi16,
+    /// No escape codes at all.
+    Plain,
+}
+
+/// The RGB values of the 16 standard ANSI colors, as rendered by xterm.
+const ANSI16_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+impl ColorMode {
+    /// Detect the color mode from stdout and the environment.
+    ///
+    /// `NO_COLOR` or a stdout which isn't a terminal disable colors entirely, `CLICOLOR_FORCE` enables them even when
+    /// stdout isn't a terminal.
+    pub fn detect() -> Self {
+        Self::detect_from(console::Term::stdout().is_term(), |name| {
+            std::env::var(name).ok()
+        })
+    }
+
+    /// Detect the color mode from whether stdout is a terminal and the environment variables returned by `get_var`.
+    fn detect_from(is_terminal: bool, get_var: impl Fn(&str) -> Option<String>) -> Self {
+        let get_var = |name| get_var(name).unwrap_or_default();
+        let force = get_var("CLICOLOR_FORCE");
+        let is_forced = !force.is_empty() && force != "0";
+        if !get_var("NO_COLOR").is_empty() || !(is_terminal || is_forced) {
+            return Self::Plain;
+        }
+
+        let color_term = get_var("COLORTERM");
+        let term = get_var("TERM");
+        if color_term == "truecolor" || color_term == "24bit" {
+            Self::TrueColor
+        } else if term == "dumb" && !is_forced {
+            Self::Plain
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+
+    /// Convert syntax highlighted regions to terminal escapes for this color mode.
+    pub fn escape_highlighted(self, regions: &[(syntect::highlighting::Style, &str)]) -> String {
+        match self {
+            // Keep the theme's background for full color terminals
+            Self::TrueColor => syntect::util::as_24_bit_terminal_escaped(regions, true),
+            Self::Ansi256 | Self::Ansi16 => {
+                let mut escaped = String::new();
+                for (style, text) in regions {
+                    let color = style.foreground;
+                    let color_code = if self == Self::Ansi256 {
+                        format!("38;5;{}", rgb_to_ansi256(color.r, color.g, color.b))
+                    } else {
+                        let index = rgb_to_ansi16(color.r, color.g, color.b);
+                        if index < 8 {
+                            (30 + index).to_string()
+                        } else {
+                            (90 + index - 8).to_string()
+                        }
+                    };
+                    let mut codes = vec![color_code];
+                    if style
+                        .font_style
+                        .contains(syntect::highlighting::FontStyle::BOLD)
+                    {
+                        codes.push("1".to_string());
+                    }
+                    if style
+                        .font_style
+                        .contains(syntect::highlighting::FontStyle::ITALIC)
+                    {
+                        codes.push("3".to_string());
+                    }
+                    if style
+                        .font_style
+                        .contains(syntect::highlighting::FontStyle::UNDERLINE)
+                    {
+                        codes.push("4".to_string());
+                    }
+                    let _ = write!(escaped, "\x1b[0;{}m{text}", codes.join(";"));
+                }
+                escaped
+            }
+            Self::Plain => regions.iter().map(|(_, text)| *text).collect(),
+        }
+    }
+}
+
+/// Find the closest color in the xterm 256 color palette.
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    // The 6x6x6 color cube
+    let to_cube = |value: u8| (u16::from(value) * 5 + 127) / 255;
+    let cube_levels = [0u8, 95, 135, 175, 215, 255];
+    let (cube_r, cube_g, cube_b) = (to_cube(r), to_cube(g), to_cube(b));
+    let cube_index = 16 + 36 * cube_r + 6 * cube_g + cube_b;
+    let cube_color = (
+        cube_levels[cube_r as usize],
+        cube_levels[cube_g as usize],
+        cube_levels[cube_b as usize],
+    );
+
+    // The 24 step grayscale ramp
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray_step = (average.saturating_sub(8) / 10).min(23);
+    let gray_level = u8::try_from(8 + gray_step * 10).unwrap_or(u8::MAX);
+    let gray_index = 232 + gray_step;
+
+    if distance((r, g, b), (gray_level, gray_level, gray_level)) < distance((r, g, b), cube_color) {
+        u8::try_from(gray_index).unwrap_or(u8::MAX)
+    } else {
+        u8::try_from(cube_index).unwrap_or(u8::MAX)
+    }
+}
+
+/// Find the closest of the 16 standard ANSI colors.
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> u8 {
+    let closest = ANSI16_PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| distance((r, g, b), **color))
+        .map_or(7, |(index, _)| index);
+    u8::try_from(closest).unwrap_or(7)
+}
+
+/// The squared Euclidean distance between two colors.
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |x: u8, y: u8| (i32::from(x) - i32::from(y)).unsigned_abs().pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+/// Load a syntax highlighting theme.
+///
+/// `name` can either be the name of one of syntect's default themes or the path to a `.tmTheme` file.
+pub fn load_theme(name: Option<&str>) -> anyhow::Result<syntect::highlighting::Theme> {
+    let name = name.unwrap_or(DEFAULT_THEME);
+    let path = crate::path_utils::expand_path(name)?;
+    if std::path::Path::new(&path).is_file() {
+        return syntect::highlighting::ThemeSet::get_theme(&path)
+            .with_context(|| format!("Loading the theme file {path}"));
+    }
+
+    let mut themes = syntect::highlighting::ThemeSet::load_defaults();
+    if let Some(theme) = themes.themes.remove(name) {
+        Ok(theme)
+    } else {
+        let available: Vec<_> = themes.themes.keys().map(String::as_str).collect();
+        anyhow::bail!(
+            "The theme {name} is not a .tmTheme file or one of the built in themes: {}",
+            available.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Detect the color mode with only the given environment variables set.
+    fn detect(is_terminal: bool, variables: &[(&str, &str)]) -> ColorMode {
+        ColorMode::detect_from(is_terminal, |name| {
+            variables
+                .iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn detects_color_modes() {
+        assert_eq!(detect(true, &[]), ColorMode::Ansi16);
+        assert_eq!(
+            detect(true, &[("TERM", "xterm-256color")]),
+            ColorMode::Ansi256
+        );
+        assert_eq!(
+            detect(
+                true,
+                &[("TERM", "xterm-256color"), ("COLORTERM", "truecolor")]
+            ),
+            ColorMode::TrueColor
+        );
+        assert_eq!(
+            detect(true, &[("COLORTERM", "24bit")]),
+            ColorMode::TrueColor
+        );
+        assert_eq!(detect(true, &[("TERM", "dumb")]), ColorMode::Plain);
+    }
+
+    #[test]
+    fn disables_colors() {
+        assert_eq!(
+            detect(false, &[("COLORTERM", "truecolor")]),
+            ColorMode::Plain
+        );
+        assert_eq!(
+            detect(true, &[("NO_COLOR", "1"), ("COLORTERM", "truecolor")]),
+            ColorMode::Plain
+        );
+        // An empty `NO_COLOR` is ignored
+        assert_eq!(detect(true, &[("NO_COLOR", "")]), ColorMode::Ansi16);
+    }
+
+    #[test]
+    fn forces_colors() {
+        assert_eq!(detect(false, &[("CLICOLOR_FORCE", "1")]), ColorMode::Ansi16);
+        assert_eq!(
+            detect(false, &[("CLICOLOR_FORCE", "1"), ("TERM", "dumb")]),
+            ColorMode::Ansi16
+        );
+        assert_eq!(
+            detect(
+                false,
+                &[("CLICOLOR_FORCE", "1"), ("COLORTERM", "truecolor")]
+            ),
+            ColorMode::TrueColor
+        );
+        assert_eq!(detect(false, &[("CLICOLOR_FORCE", "0")]), ColorMode::Plain);
+        assert_eq!(
+            detect(false, &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]),
+            ColorMode::Plain
+        );
+    }
+
+    #[test]
+    fn loads_built_in_themes() {
+        assert!(load_theme(None).is_ok());
+        assert_eq!(
+            load_theme(Some("InspiredGitHub")).unwrap().name.as_deref(),
+            Some("GitHub")
+        );
+        let error = load_theme(Some("no-such-theme")).unwrap_err().to_string();
+        assert!(error.starts_with(
+            "The theme no-such-theme is not a .tmTheme file or one of the built in themes: "
+        ));
+        assert!(error.contains("base16-ocean.dark"));
+    }
+
+    #[test]
+    fn loads_theme_files() {
+        let path =
+            std::env::temp_dir().join(format!("ask-theme-test-{}.tmTheme", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Test</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#112233</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#,
+        )
+        .unwrap();
+        let theme = load_theme(path.to_str());
+        std::fs::write(&path, "not a theme").unwrap();
+        let invalid = load_theme(path.to_str());
+        std::fs::remove_file(&path).unwrap();
+
+        let theme = theme.unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Test"));
+        assert_eq!(
+            theme.settings.foreground,
+            Some(syntect::highlighting::Color {
+                r: 0x11,
+                g: 0x22,
+                b: 0x33,
+                a: 0xff
+            })
+        );
+        assert!(format!("{:#}", invalid.unwrap_err()).starts_with("Loading the theme file "));
+    }
+}
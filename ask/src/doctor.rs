@@ -0,0 +1,264 @@
+//! The `ask doctor` command which diagnoses problems with the setup.
+
+use anyhow::Context;
+use argh::FromArgs;
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "doctor")]
+/// Check the config, the LLM provider, and the tools for problems.
+pub struct DoctorCommand {}
+
+/// How long each request to check a service can take.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The outcome of the checks.
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    errors: usize,
+}
+
+impl Report {
+    /// Print a section heading.
+    fn section(&self, name: &str) {
+        println!("{}", console::style(name).bold());
+    }
+
+    /// Report a check which passed.
+    fn ok(&self, message: impl std::fmt::Display) {
+        println!("  {} {message}", console::style("✓").green());
+    }
+
+    /// Report a problem which might stop some things from working.
+    fn warn(&mut self, message: impl std::fmt::Display, hint: Option<String>) {
+        self.warnings += 1;
+        println!("  {} {message}", console::style("!").yellow());
+        if let Some(hint) = hint {
+            println!("    {}", console::style(hint).dim());
+        }
+    }
+
+    /// Report a problem which stops `ask` from working.
+    fn error(&mut self, message: impl std::fmt::Display, hint: Option<String>) {
+        self.errors += 1;
+        println!("  {} {message}", console::style("✗").red());
+        if let Some(hint) = hint {
+            println!("    {}", console::style(hint).dim());
+        }
+    }
+}
+
+/// Check the config file, returning the config if it could be loaded.
+fn check_config(report: &mut Report) -> anyhow::Result<Option<productivity_config::Config>> {
+    report.section("Config");
+    let path = productivity_config::Config::get_config_file_path()?;
+    if !path.exists() {
+        report.warn(
+            format!("There is no config file at {}", path.display()),
+            Some("Run any `ask` command to create the default config file".to_string()),
+        );
+        return Ok(None);
+    }
+    report.ok(format!("The config file is at {}", path.display()));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::fs::metadata(&path)
+            .with_context(|| format!("Reading the metadata of {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 == 0 {
+            report.ok("The config file is only accessible by its owner");
+        } else {
+            report.warn(
+                format!(
+                    "The config file is accessible by other users (mode {:o}) and can contain API keys",
+                    mode & 0o777
+                ),
+                Some(format!("Run `chmod 600 {}`", path.display())),
+            );
+        }
+    }
+
+    match productivity_config::Config::load(&path) {
+        Ok(config) => {
+            report.ok("The config file is valid");
+            Ok(Some(config))
+        }
+        Err(e) => {
+            report.error(format!("The config file is invalid: {e:#}"), None);
+            Ok(None)
+        }
+    }
+}
+
+/// Check the LLM provider is configured, reachable, and has the model.
+async fn check_provider(
+    report: &mut Report,
+    client: &reqwest::Client,
+    config: &productivity_config::Config,
+) {
+    report.section("LLM provider");
+    let url_base = config.llm_provider.get_url_base();
+    match &config.llm_provider {
+        productivity_config::LlmProvider::Anthropic { api_key } => {
+            report.ok("The provider is Anthropic");
+            if api_key.is_empty() {
+                report.error(
+                    "The Anthropic API key is not set",
+                    Some("Set `llm_provider.api_key` in the config file".to_string()),
+                );
+                return;
+            }
+            report.ok("The Anthropic API key is set");
+
+            // Getting the model itself rather than listing the models doesn't depend on which page it's on
+            let model = crate::anthropic_client::AnthropicQuery::default().model;
+            let response = client
+                .get(format!("{url_base}/v1/models/{model}"))
+                .header("x-api-key", api_key)
+                .header("anthropic-version", "2023-06-01")
+                .send()
+                .await;
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    report.error(format!("Could not reach {url_base}: {e}"), None);
+                    return;
+                }
+            };
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+                report.error("The Anthropic API rejected the API key", None);
+                return;
+            }
+            report.ok(format!("{url_base} is reachable"));
+
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                report.error(format!("The model {model} is not available"), None);
+            } else if let Err(e) = response.error_for_status() {
+                report.error(format!("The Anthropic API returned an error: {e}"), None);
+            } else {
+                report.ok(format!("The model {model} is available"));
+            }
+        }
+        productivity_config::LlmProvider::Ollama { .. } => {
+            report.ok("The provider is Ollama");
+            let client = match crate::ollama::OllamaClient::from_config(config) {
+                Ok(client) => client,
+                Err(e) => {
+                    report.error(format!("The Ollama config is invalid: {e:#}"), None);
+                    return;
+                }
+            };
+            match client.get_tags().await {
+                Ok(tags) => {
+                    report.ok(format!("{url_base} is reachable"));
+                    if tags.models.iter().any(|model| model.name == client.model) {
+                        report.ok(format!("The model {} is downloaded", client.model));
+                    } else {
+                        report.warn(
+                            format!("The model {} is not downloaded", client.model),
+                            Some(
+                                "Run `ask models pull` or it will be downloaded on first use"
+                                    .to_string(),
+                            ),
+                        );
+                    }
+                }
+                Err(e) => report.error(
+                    format!("Could not reach {url_base}: {e}"),
+                    Some(
+                        "Check Ollama is running and `llm_provider.url_base` is correct"
+                            .to_string(),
+                    ),
+                ),
+            }
+        }
+    }
+}
+
+/// Check which tools can be used.
+fn check_tools(report: &mut Report, config: &productivity_config::Config) {
+    report.section("Tools");
+    for (definition, tool) in crate::tools::get_all_tools(config).iter() {
+        let name = &definition.name;
+        if !config.tool_policy.is_tool_enabled(name) {
+            println!(
+                "  {} {name} is disabled by the tool policy",
+                console::style("-").dim()
+            );
+            continue;
+        }
+
+        match tool.get_prequisites().is_satisfied() {
+            Ok(()) => report.ok(name),
+            Err(failures) => {
+                for failure in failures {
+                    report.warn(format!("{name}: {failure}"), failure.get_hint());
+                }
+            }
+        }
+    }
+}
+
+/// Check the `go/` links config.
+async fn check_golink(
+    report: &mut Report,
+    client: &reqwest::Client,
+    config: &productivity_config::Config,
+) {
+    report.section("go/ links");
+    if config.get_orgorg_api_key().is_some() {
+        report.ok("The orgorg API key is set");
+    } else {
+        report.warn(
+            "The orgorg API key is not set so `go` won't work",
+            Some("Set `orgorg_api_key` in the config file or the ORGORG_API_KEY environment variable".to_string()),
+        );
+    }
+
+    let url_base = config.get_orgorg_url_base();
+    match client.get(&url_base).send().await {
+        Ok(_) => report.ok(format!("{url_base} is reachable")),
+        Err(e) => report.warn(format!("Could not reach {url_base}: {e}"), None),
+    }
+}
+
+impl DoctorCommand {
+    /// Run the checks and print a report.
+    pub fn run(&self) -> anyhow::Result<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Creating the async runtime")?;
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Creating the HTTP client")?;
+
+        let mut report = Report::default();
+        let config = check_config(&mut report)?;
+        if let Some(config) = config {
+            runtime.block_on(check_provider(&mut report, &client, &config));
+            check_tools(&mut report, &config);
+            runtime.block_on(check_golink(&mut report, &client, &config));
+        }
+
+        println!();
+        if report.errors > 0 {
+            anyhow::bail!(
+                "Found {} error(s) and {} warning(s)",
+                report.errors,
+                report.warnings
+            );
+        }
+        if report.warnings > 0 {
+            println!("Found {} warning(s)", report.warnings);
+        } else {
+            println!("No problems found");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,407 @@
+//! Export of answers and transcripts to Markdown or HTML documents.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::response_parsing::Printer;
+
+/// A part of a conversation with the LLM.
+enum TranscriptEntry {
+    /// The question the user asked.
+    Question(String),
+    /// Markdown text sent by the LLM.
+    Answer(String),
+    /// A tool invocation and the result sent back to the LLM.
+    ToolCall {
+        name: String,
+        input: serde_json::Value,
+        result: String,
+    },
+}
+
+/// A record of a conversation with the LLM which can be exported.
+#[derive(Default)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+}
+
+/// Get the Markdown parser options matching the ones used for the terminal.
+fn markdown_options() -> pulldown_cmark::Options {
+    let mut options = pulldown_cmark::Options::empty();
+    options.insert(pulldown_cmark::Options::ENABLE_GFM);
+    options.insert(pulldown_cmark::Options::ENABLE_TABLES);
+    options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
+    options.insert(pulldown_cmark::Options::ENABLE_TASKLISTS);
+    options
+}
+
+/// Remove the `# Thoughts` section from an answer, as is done when printing it to the terminal.
+fn strip_thoughts(markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, markdown_options()).into_offset_iter();
+    let mut thoughts_start = None;
+    let mut current_heading = None;
+    let mut removed = vec![];
+    for (event, range) in parser {
+        match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading { level, .. }) => {
+                if let Some(start) = thoughts_start.take() {
+                    removed.push(start..range.start);
+                }
+                current_heading = Some((level, range.start));
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Heading(_)) => {
+                current_heading = None;
+            }
+            pulldown_cmark::Event::Text(text) => {
+                if let Some((pulldown_cmark::HeadingLevel::H1, start)) = current_heading {
+                    if text.trim().eq_ignore_ascii_case("Thoughts") {
+                        thoughts_start = Some(start);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut stripped = String::with_capacity(markdown.len());
+    let mut position = 0;
+    for range in removed {
+        stripped.push_str(&markdown[position..range.start]);
+        position = range.end;
+    }
+    stripped.push_str(&markdown[position..]);
+    stripped.trim().to_string()
+}
+
+/// Wrap content in a fenced code block, using a fence longer than any backtick run in the content.
+fn fenced(language: &str, content: &str) -> String {
+    let mut longest_run = 0;
+    let mut run = 0;
+    for character in content.chars() {
+        if character == '`' {
+            run += 1;
+            longest_run = longest_run.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    let fence = "`".repeat((longest_run + 1).max(3));
+    format!(
+        "{fence}{language}\n{}\n{fence}",
+        content.trim_end_matches('\n')
+    )
+}
+
+/// Escape text for use in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Check whether a link destination is safe to open from an HTML file: a relative URL or an http, https, or mailto URL.
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in schemes, so `java\tscript:` is still a script
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    let Some((scheme, _)) = url.split_once(':') else {
+        return true;
+    };
+    // A colon after a path, query, or fragment isn't a scheme
+    if scheme.contains(['/', '?', '#']) {
+        return true;
+    }
+    ["http", "https", "mailto"]
+        .iter()
+        .any(|safe| scheme.eq_ignore_ascii_case(safe))
+}
+
+impl Transcript {
+    /// Record the question the user asked.
+    pub fn add_question(&mut self, question: &str) {
+        self.entries
+            .push(TranscriptEntry::Question(question.to_string()));
+    }
+
+    /// Record the raw Markdown of a response from the LLM.
+    pub fn add_answer(&mut self, markdown: &str) {
+        if !markdown.trim().is_empty() {
+            self.entries
+                .push(TranscriptEntry::Answer(markdown.to_string()));
+        }
+    }
+
+    /// Record a tool invocation and the result sent back to the LLM.
+    pub fn add_tool_call(&mut self, name: &str, input: &serde_json::Value, result: &str) {
+        self.entries.push(TranscriptEntry::ToolCall {
+            name: name.to_string(),
+            input: input.clone(),
+            result: result.to_string(),
+        });
+    }
+
+    /// Render the transcript as Markdown.
+    ///
+    /// Only the final answer is included unless `full` is set, in which case the question and tool calls are included too.
+    fn to_markdown(&self, full: bool) -> String {
+        if !full {
+            return self
+                .entries
+                .iter()
+                .rev()
+                .find_map(|entry| match entry {
+                    TranscriptEntry::Answer(markdown) => Some(strip_thoughts(markdown)),
+                    _ => None,
+                })
+                .unwrap_or_default();
+        }
+
+        let mut sections = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            sections.push(match entry {
+                TranscriptEntry::Question(question) => format!("## Question\n\n{question}"),
+                TranscriptEntry::Answer(markdown) => {
+                    format!("## Answer\n\n{}", strip_thoughts(markdown))
+                }
+                TranscriptEntry::ToolCall {
+                    name,
+                    input,
+                    result,
+                } => {
+                    let input =
+                        serde_json::to_string_pretty(input).unwrap_or_else(|_| input.to_string());
+                    format!(
+                        "## Tool call: `{name}`\n\n{}\n\n**Result**\n\n{}",
+                        fenced("json", &input),
+                        fenced("", result)
+                    )
+                }
+            });
+        }
+        sections.join("\n\n")
+    }
+
+    /// Render Markdown as a standalone HTML document, highlighting code blocks with the printer's theme.
+    fn to_html<W: std::io::Write>(markdown: &str, printer: &Printer<W>) -> anyhow::Result<String> {
+        let mut events = vec![];
+        let mut code_block: Option<(String, String)> = None;
+        for event in pulldown_cmark::Parser::new_ext(markdown, markdown_options()) {
+            match event {
+                pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(kind)) => {
+                    let language = match kind {
+                        pulldown_cmark::CodeBlockKind::Fenced(language) => language.to_string(),
+                        pulldown_cmark::CodeBlockKind::Indented => String::new(),
+                    };
+                    code_block = Some((language, String::new()));
+                }
+                pulldown_cmark::Event::Text(text) if code_block.is_some() => {
+                    if let Some((_, content)) = &mut code_block {
+                        content.push_str(&text);
+                    }
+                }
+                pulldown_cmark::Event::End(pulldown_cmark::TagEnd::CodeBlock) => {
+                    if let Some((language, content)) = code_block.take() {
+                        events.push(pulldown_cmark::Event::Html(
+                            printer.highlight_html(&language, &content)?.into(),
+                        ));
+                    }
+                }
+                // Raw HTML from the LLM or a tool result could run scripts when the file is opened, so it's shown as text
+                pulldown_cmark::Event::Html(html) | pulldown_cmark::Event::InlineHtml(html) => {
+                    events.push(pulldown_cmark::Event::Text(html));
+                }
+                // Links from the LLM or a tool result could also run scripts, for example `javascript:` links
+                pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }) if !is_safe_url(&dest_url) => {
+                    events.push(pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link {
+                        link_type,
+                        dest_url: "#".into(),
+                        title,
+                        id,
+                    }));
+                }
+                pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }) if !is_safe_url(&dest_url) => {
+                    events.push(pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image {
+                        link_type,
+                        dest_url: "#".into(),
+                        title,
+                        id,
+                    }));
+                }
+                event => events.push(event),
+            }
+        }
+
+        let mut body = String::with_capacity(markdown.len() * 2);
+        pulldown_cmark::html::push_html(&mut body, events.into_iter());
+
+        Ok(format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }}
+pre {{ padding: 1em; overflow-x: auto; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 0.25em 0.5em; }}
+blockquote {{ border-left: 4px solid #ccc; margin-left: 0; padding-left: 1em; }}
+</style>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+            escape_html(
+                &chrono::Local::now()
+                    .format("ask %Y-%m-%d %H:%M")
+                    .to_string()
+            )
+        ))
+    }
+
+    /// Write the transcript to a file, as HTML if the file has an `.html` or `.htm` extension and Markdown otherwise.
+    pub fn export<W: std::io::Write>(
+        &self,
+        path: &Path,
+        full: bool,
+        printer: &Printer<W>,
+    ) -> anyhow::Result<()> {
+        let mut markdown = self.to_markdown(full);
+        markdown.push('\n');
+        let is_html = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+            });
+        let contents = if is_html {
+            Self::to_html(&markdown, printer)?
+        } else {
+            markdown
+        };
+        std::fs::write(path, contents)
+            .with_context(|| format!("Writing the answer to {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a transcript of a conversation with a tool call.
+    fn transcript() -> Transcript {
+        let mut transcript = Transcript::default();
+        transcript.add_question("What is in src?");
+        transcript.add_answer("# Thoughts\n\nI should look.\n\n# Plan\n\nList it.");
+        transcript.add_tool_call(
+            "read_files",
+            &serde_json::json!({"path": "src"}),
+            "main.rs\n```\n",
+        );
+        transcript.add_answer("  ");
+        transcript.add_answer("# Thoughts\n\nDone.\n\n# Result\n\nOnly `main.rs`.");
+        transcript
+    }
+
+    /// Create a printer which doesn't print anything.
+    fn printer() -> Printer<Vec<u8>> {
+        Printer::with_writer(
+            Vec::new(),
+            crate::color_mode::load_theme(None).unwrap(),
+            crate::color_mode::ColorMode::Plain,
+            Some(80),
+        )
+    }
+
+    #[test]
+    fn exports_the_final_answer_as_markdown() {
+        assert_eq!(
+            transcript().to_markdown(false),
+            "# Result\n\nOnly `main.rs`."
+        );
+        assert_eq!(Transcript::default().to_markdown(false), "");
+    }
+
+    #[test]
+    fn exports_full_transcripts_as_markdown() {
+        assert_eq!(
+            transcript().to_markdown(true),
+            "## Question\n\nWhat is in src?\n\n## Answer\n\n# Plan\n\nList it.\n\n## Tool call: `read_files`\n\n```json\n{\n  \"path\": \"src\"\n}\n```\n\n**Result**\n\n````\nmain.rs\n```\n````\n\n## Answer\n\n# Result\n\nOnly `main.rs`."
+        );
+    }
+
+    #[test]
+    fn exports_html() {
+        let html = Transcript::to_html(
+            "# Result\n\n<script>alert(1)</script>\n\nRun <b>this</b>:\n\n```sh\necho '<hi>'\n```\n",
+            &printer(),
+        )
+        .unwrap();
+        assert!(html
+            .starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ask "));
+        assert!(html.contains("<h1>Result</h1>\n"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("<p>Run &lt;b&gt;this&lt;/b&gt;:</p>"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>"));
+        // Code blocks are highlighted with inline styles
+        assert!(html.contains("<pre style=\""));
+        assert!(html.contains("&lt;hi&gt;"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn rewrites_unsafe_links() {
+        let html = Transcript::to_html(
+            "[a](javascript:alert(1)) [b](<java\tscript:alert(1)>) ![c](data:text/html,hi) [d](https://example.com) \
+             [e](mailto:me@example.com) [f](docs/a:b.md) [g](#top)\n",
+            &printer(),
+        )
+        .unwrap();
+        assert!(!html.contains("script:"));
+        assert!(!html.contains("data:"));
+        assert!(html.contains(r##"<a href="#">a</a>"##));
+        assert!(html.contains(r##"<a href="#">b</a>"##));
+        assert!(html.contains(r##"<img src="#" alt="c" />"##));
+        assert!(html.contains(r#"<a href="https://example.com">d</a>"#));
+        assert!(html.contains(r#"<a href="mailto:me@example.com">e</a>"#));
+        assert!(html.contains(r#"<a href="docs/a:b.md">f</a>"#));
+        assert!(html.contains(r##"<a href="#top">g</a>"##));
+    }
+
+    #[test]
+    fn exports_by_extension() {
+        let directory =
+            std::env::temp_dir().join(format!("ask-export-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let transcript = transcript();
+        transcript
+            .export(&directory.join("answer.md"), false, &printer())
+            .unwrap();
+        transcript
+            .export(&directory.join("answer.HTML"), true, &printer())
+            .unwrap();
+        let markdown = std::fs::read_to_string(directory.join("answer.md")).unwrap();
+        let html = std::fs::read_to_string(directory.join("answer.HTML")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(markdown, "# Result\n\nOnly `main.rs`.\n");
+        assert!(html.contains("<h2>Question</h2>"));
+        assert!(html.contains("<h2>Tool call: <code>read_files</code></h2>"));
+    }
+}
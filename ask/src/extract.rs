@@ -0,0 +1,269 @@
+//! Extraction of code blocks from answers to files, the clipboard, or a shell.
+
+use std::io::Write;
+
+use anyhow::Context;
+
+use crate::llm_client::TextOutput;
+use crate::response_parsing::Printer;
+
+/// Commands which copy their stdin to the clipboard, in order of preference.
+const CLIPBOARD_COMMANDS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/// A code block from an answer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CodeBlock {
+    pub language: String,
+    pub content: String,
+}
+
+/// Get the code blocks in a parsed answer.
+pub fn get_code_blocks(outputs: &[TextOutput]) -> Vec<CodeBlock> {
+    outputs
+        .iter()
+        .filter_map(|output| match output {
+            TextOutput::CodeBlock { language, content } => Some(CodeBlock {
+                language: language.clone(),
+                content: content.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Describe a code block for the user to pick it, with its number, language, and first line.
+fn describe_block(block_number: usize, block: &CodeBlock) -> String {
+    let first_line = block.content.lines().next().unwrap_or_default();
+    format!("[{block_number}] {} {first_line}", block.language)
+}
+
+/// Get the interpreter which can run a code block and the script to run, `None` if the block can't be run.
+///
+/// Only the commands of `console` blocks are run, which are the lines starting with a `$ ` prompt, since the other
+/// lines are their output.
+fn get_script(block: &CodeBlock) -> Option<(&'static str, String)> {
+    let interpreter = match block.language.to_lowercase().as_str() {
+        "console" | "shell-session" => {
+            let commands: Vec<&str> = block
+                .content
+                .lines()
+                .filter_map(|line| line.trim_start().strip_prefix("$ "))
+                .collect();
+            if commands.is_empty() {
+                return None;
+            }
+            return Some(("sh", commands.join("\n")));
+        }
+        "" | "sh" | "shell" => "sh",
+        "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "python" | "py" | "python3" => "python3",
+        _ => return None,
+    };
+    Some((interpreter, block.content.clone()))
+}
+
+/// Save a code block to a file chosen by the user.
+fn save(block: &CodeBlock, block_number: usize, printer: &Printer) -> anyhow::Result<()> {
+    let path: String = dialoguer::Input::new()
+        .with_prompt("Save to")
+        .default(format!(
+            "block-{block_number}.{}",
+            printer.suggest_extension(&block.language)
+        ))
+        .interact_text()
+        .context("From input")?;
+    let path = crate::path_utils::expand_path(&path)?;
+
+    if std::path::Path::new(&path).exists()
+        && !dialoguer::Confirm::new()
+            .with_prompt(format!("{path} already exists, overwrite it?"))
+            .interact()
+            .context("From confirm")?
+    {
+        return Ok(());
+    }
+
+    std::fs::write(&path, &block.content).with_context(|| format!("Writing {path}"))?;
+    println!("Saved code block {block_number} to {path}");
+    Ok(())
+}
+
+/// Copy a code block to the clipboard with the first available clipboard command.
+fn copy(block: &CodeBlock, block_number: usize) -> anyhow::Result<()> {
+    let Some((binary, arguments)) = CLIPBOARD_COMMANDS
+        .iter()
+        .find(|(binary, _)| which::which(binary).is_ok())
+    else {
+        let binaries: Vec<_> = CLIPBOARD_COMMANDS
+            .iter()
+            .map(|(binary, _)| *binary)
+            .collect();
+        anyhow::bail!(
+            "No clipboard command found, install one of {}",
+            binaries.join(", ")
+        );
+    };
+
+    let mut child = std::process::Command::new(binary)
+        .args(*arguments)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Running {binary}"))?;
+    child
+        .stdin
+        .take()
+        .context("Getting the clipboard command's stdin")?
+        .write_all(block.content.as_bytes())
+        .with_context(|| format!("Writing to {binary}"))?;
+    let status = child
+        .wait()
+        .with_context(|| format!("Waiting for {binary}"))?;
+    if !status.success() {
+        anyhow::bail!("{binary} failed with {status}");
+    }
+
+    println!("Copied code block {block_number} to the clipboard");
+    Ok(())
+}
+
+/// Run a code block with its interpreter after the user confirms it.
+fn run(block: &CodeBlock, block_number: usize) -> anyhow::Result<()> {
+    let Some((interpreter, script)) = get_script(block) else {
+        anyhow::bail!(
+            "Running {} code blocks isn't supported, save it to a file instead",
+            block.language
+        );
+    };
+
+    println!("{}", console::style(&script).dim());
+    let confirmed = dialoguer::Confirm::new()
+        .with_prompt(format!("Run code block {block_number} with {interpreter}?"))
+        .default(false)
+        .interact()
+        .context("From confirm")?;
+    if !confirmed {
+        return Ok(());
+    }
+
+    let status = std::process::Command::new(interpreter)
+        .arg("-c")
+        .arg(&script)
+        .status()
+        .with_context(|| format!("Running {interpreter}"))?;
+    if !status.success() {
+        anyhow::bail!("Code block {block_number} failed with {status}");
+    }
+    Ok(())
+}
+
+/// Let the user pick a code block and save, copy, or run it.
+pub fn extract(blocks: &[CodeBlock], printer: &Printer) -> anyhow::Result<()> {
+    if blocks.is_empty() {
+        println!("The answer has no code blocks to extract");
+        return Ok(());
+    }
+
+    let choices: Vec<String> = blocks
+        .iter()
+        .enumerate()
+        .map(|(index, block)| describe_block(index + 1, block))
+        .collect();
+    let Some(index) = dialoguer::Select::new()
+        .with_prompt("Code block to extract")
+        .items(&choices)
+        .default(blocks.len() - 1)
+        .interact_opt()
+        .context("From select")?
+    else {
+        return Ok(());
+    };
+
+    let block = &blocks[index];
+    let block_number = index + 1;
+    let Some(action) = dialoguer::Select::new()
+        .with_prompt(format!("What to do with code block {block_number}"))
+        .items(&["Save to a file", "Copy to the clipboard", "Run it"])
+        .default(0)
+        .interact_opt()
+        .context("From select")?
+    else {
+        return Ok(());
+    };
+
+    match action {
+        0 => save(block, block_number, printer),
+        1 => copy(block, block_number),
+        _ => run(block, block_number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a code block.
+    fn block(language: &str, content: &str) -> CodeBlock {
+        CodeBlock {
+            language: language.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn gets_code_blocks() {
+        let mut outputs = vec![];
+        crate::response_parsing::parse_text(
+            "Run this:\n\n```sh\nls -la\n```\n\nThen `this`:\n\n```python\nprint(1)\nprint(2)\n```\n",
+            &mut outputs,
+        );
+        let blocks = get_code_blocks(&outputs);
+        assert_eq!(
+            blocks,
+            [block("sh", "ls -la"), block("python", "print(1)\nprint(2)")]
+        );
+        assert_eq!(describe_block(2, &blocks[1]), "[2] python print(1)");
+        assert_eq!(describe_block(1, &block("", "")), "[1]  ");
+        assert!(get_code_blocks(&[TextOutput::InlineCode("ls".to_string())]).is_empty());
+    }
+
+    #[test]
+    fn maps_languages_to_interpreters() {
+        for (language, interpreter) in [
+            ("", "sh"),
+            ("sh", "sh"),
+            ("Shell", "sh"),
+            ("bash", "bash"),
+            ("zsh", "zsh"),
+            ("fish", "fish"),
+            ("py", "python3"),
+            ("Python", "python3"),
+        ] {
+            assert_eq!(
+                get_script(&block(language, "echo hi\n")),
+                Some((interpreter, "echo hi\n".to_string())),
+                "{language}"
+            );
+        }
+        assert_eq!(get_script(&block("rust", "fn main() {}")), None);
+        assert_eq!(get_script(&block("json", "{}")), None);
+    }
+
+    #[test]
+    fn runs_only_the_commands_of_console_blocks() {
+        assert_eq!(
+            get_script(&block(
+                "console",
+                "$ ls\nCargo.toml  src\n  $ cargo build\n   Compiling ask\n"
+            )),
+            Some(("sh", "ls\ncargo build".to_string()))
+        );
+        assert_eq!(get_script(&block("console", "Cargo.toml  src\n")), None);
+    }
+}
@@ -1,10 +1,37 @@
+use crate::tools::ToolRegistry;
+
 /// A text output section.
+#[derive(Debug, Clone, PartialEq)]
 pub enum TextOutput {
     Text(String),
     Bold(String),
     Italic(String),
+    Strikethrough(String),
     InlineCode(String),
-    CodeBlock { language: String, content: String },
+    CodeBlock {
+        language: String,
+        content: String,
+    },
+    Link {
+        text: String,
+        url: String,
+    },
+    Table {
+        alignments: Vec<pulldown_cmark::Alignment>,
+        /// The rows of the table, the first row is the header.
+        rows: Vec<Vec<String>>,
+    },
+    /// The start of a list item with the item's marker, continuation lines are indented past the marker.
+    ListItemStart(String),
+    ListItemEnd,
+    /// A task list checkbox, `true` if the task is done.
+    TaskMarker(bool),
+    /// The start of a block quote, subsequent lines get a gutter until the matching end.
+    BlockQuoteStart,
+    BlockQuoteEnd,
+    /// Raw HTML.
+    Html(String),
+    HorizontalRule,
     Newline,
 }
 
@@ -19,13 +46,15 @@ pub struct ToolInvocation {
 pub struct LlmResponse {
     /// The LLMs text response.
     pub text: Vec<TextOutput>,
+    /// The LLMs text response as the raw Markdown it sent.
+    pub raw_text: String,
     /// A list of tool invocations.
     pub tool_invocations: Vec<ToolInvocation>,
 }
 
 pub trait LlmQuery: serde::Serialize + Clone {
-    /// Create a query.
-    fn create_query(system_prompt: String) -> Self;
+    /// Create a query which offers the registry's tools to the LLM.
+    fn create_query(system_prompt: String, tools: &ToolRegistry) -> Self;
     /// Add a question to the query.
     fn add_question(&mut self, question: String);
     /// Add tool use results to a query.
@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use anyhow::Context;
 use argh::FromArgs;
 
@@ -7,24 +5,120 @@ use llm_client::{LlmClient, LlmQuery};
 use ollama::OllamaClient;
 
 mod anthropic_client;
+mod audit;
+mod color_mode;
+mod doctor;
+mod export;
+mod extract;
 mod host_info;
 mod llm_client;
 mod ollama;
 mod path_utils;
+mod redaction;
 mod response_parsing;
+mod status;
 mod tools;
+mod truncation;
 
 #[derive(FromArgs)]
-/// Ask a question.
+/// Ask a question, or run one of the `ask` commands.
+#[argh(note = "ask audit         browse the audit log of tool invocations
+ask models        list, pull, remove, and show Ollama models
+ask doctor        check the config, the LLM provider, and the tools for problems
+ask -- <question> ask a question which starts with the name of a command")]
 struct Ask {
     #[argh(switch, short = 'v')]
     /// verbose mode
     verbose: bool,
+    #[argh(option, short = 'o')]
+    /// write the final answer to a Markdown file, or an HTML file if the path ends in .html
+    output: Option<std::path::PathBuf>,
+    #[argh(switch)]
+    /// include the question and tool calls in the --output file
+    transcript: bool,
+    #[argh(switch, short = 'x')]
+    /// pick a code block from the answer to save, copy, or run
+    extract: bool,
+    #[argh(option, from_str_fn(parse_tool_names))]
+    /// only offer these comma separated tools to the model
+    tools: Option<Vec<String>>,
+    #[argh(switch)]
+    /// don't offer any tools to the model
+    no_tools: bool,
     #[argh(positional, greedy)]
     /// the question to ask
     question: Vec<String>,
 }
 
+#[derive(FromArgs)]
+#[argh(subcommand)]
+/// Commands which can be run instead of asking a question.
+enum Command {
+    Audit(audit::AuditCommand),
+    Models(ollama::models::ModelsCommand),
+    Doctor(doctor::DoctorCommand),
+}
+
+/// Parse the comma separated tool names of `--tools`.
+fn parse_tool_names(value: &str) -> Result<Vec<String>, String> {
+    let names: Vec<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
+        .collect();
+    if names.is_empty() {
+        return Err("--tools needs at least one tool name, use --no-tools for none".to_string());
+    }
+    Ok(names)
+}
+
+/// Check whether the question was passed after a `--` separator, in which case it's never a command.
+fn is_after_separator(args: &[String], question: &[String]) -> bool {
+    args.iter()
+        .position(|arg| arg == "--")
+        .is_some_and(|separator| args.len() - separator - 1 == question.len())
+}
+
+/// Parse a command if the first word of the question is a command name.
+///
+/// Questions which merely start with a command name, such as "doctor who?", aren't valid arguments for the command so
+/// they're asked instead. Arguments which look like they're meant for the command, a flag or one of its subcommands,
+/// return argh's help or error instead.
+fn parse_command(question: &[String]) -> Result<Option<Command>, argh::EarlyExit> {
+    let Some((name, arguments)) = question.split_first() else {
+        return Ok(None);
+    };
+    if !<Command as argh::SubCommands>::COMMANDS
+        .iter()
+        .any(|command| command.name == name)
+    {
+        return Ok(None);
+    }
+
+    let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+    match Command::from_args(&["ask", name], &arguments) {
+        Ok(command) => Ok(Some(command)),
+        Err(early_exit) if early_exit.status.is_ok() => Err(early_exit),
+        Err(early_exit) => {
+            let has_flag = arguments.iter().any(|argument| argument.starts_with('-'));
+            // Asking for the help of a subcommand only succeeds if it exists
+            let has_subcommand = arguments.first().is_some_and(|subcommand| {
+                Command::from_args(&["ask", name], &[subcommand, "--help"])
+                    .is_err_and(|help| help.status.is_ok())
+            });
+            if has_flag || has_subcommand {
+                return Err(early_exit);
+            }
+            tracing::info!(
+                "Asking the question since it isn't a valid {name} command: {}",
+                early_exit.output
+            );
+            Ok(None)
+        }
+    }
+}
+
 fn get_system_prompt(settings: &productivity_config::Config) -> String {
     let terminal = console::Term::stdout();
     let terminal_width = terminal.size_checked().map_or(80, |s| s.1) as usize;
@@ -108,34 +202,185 @@ fn get_system_prompt(settings: &productivity_config::Config) -> String {
     )
 }
 
+/// Run a tool invocation if the tool policy and the tool's prerequisites allow it.
+///
+/// Returns the outcome for the audit log alongside the response for the LLM.
+async fn run_tool_invocation(
+    tool_registry: &tools::ToolRegistry,
+    config: &productivity_config::Config,
+    name: &str,
+    input: serde_json::Value,
+) -> (audit::AuditStatus, anyhow::Result<String>) {
+    if !config.tool_policy.is_tool_enabled(name) {
+        return (
+            audit::AuditStatus::Denied,
+            Ok(format!(
+                "The {name} tool is disabled by the user's tool policy"
+            )),
+        );
+    }
+
+    let Some(tool) = tool_registry.get(name) else {
+        return (
+            audit::AuditStatus::Unavailable,
+            Ok(format!("The {name} tool is not available")),
+        );
+    };
+
+    if let Some(rule) = config.tool_policy.rules.get(name) {
+        if let Err(message) = tools::check_enforced(rule, tool.get_enforced_rule_fields())
+            .and_then(|()| tool.check_policy(&input, rule))
+        {
+            return (
+                audit::AuditStatus::Denied,
+                Ok(format!(
+                    "The user's tool policy does not allow this invocation of {name}:\n{message}"
+                )),
+            );
+        }
+    }
+
+    // The prerequisites which don't depend on the input were checked before the tool was offered
+    if let Err(failures) = tool.get_prequisites().check_input(&input).await {
+        return (
+            audit::AuditStatus::Unavailable,
+            Ok(format!(
+                "Could not run {name}:\n{}",
+                tools::describe_failures(&failures)
+            )),
+        );
+    }
+
+    match tool.run(input).await {
+        Ok(tool_response) => (audit::AuditStatus::Success, Ok(tool_response)),
+        Err(e) => (audit::AuditStatus::Error, Err(e)),
+    }
+}
+
+/// Get the tools to offer the model in this run.
+///
+/// Tools disabled by the tool policy, tools whose prerequisites aren't satisfied, and tools not selected with `--tools`
+/// are left out so the model doesn't waste turns on them.
+fn get_tools(
+    config: &productivity_config::Config,
+    ask: &Ask,
+) -> anyhow::Result<tools::ToolRegistry> {
+    if ask.no_tools {
+        if ask.tools.is_some() {
+            anyhow::bail!("--tools and --no-tools can't be used together");
+        }
+        return Ok(tools::ToolRegistry::default());
+    }
+
+    // Unknown names are reported before any plugin is run or MCP server is started
+    if let Some(selected) = &ask.tools {
+        let mut names = tools::get_tool_names(config);
+        let unknown: Vec<&str> = selected
+            .iter()
+            .filter(|name| {
+                !names.iter().any(|known| match known.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => known == *name,
+                })
+            })
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            names.sort_unstable();
+            names.dedup();
+            anyhow::bail!(
+                "Unknown tool(s) {}, the tools are: {}",
+                unknown.join(", "),
+                names.join(", ")
+            );
+        }
+    }
+
+    let mut registry = tools::get_selected_tools(config, ask.tools.as_deref());
+    for name in ask.tools.iter().flatten() {
+        if registry.get(name).is_none() {
+            tracing::warn!(
+                "The {name} tool is unavailable since its plugin or MCP server didn't provide it"
+            );
+        }
+    }
+
+    registry.retain(|definition, tool| {
+        let is_selected = ask
+            .tools
+            .as_ref()
+            .map(|selected| selected.contains(&definition.name));
+        if is_selected == Some(false) {
+            return false;
+        }
+        if !config.tool_policy.is_tool_enabled(&definition.name) {
+            if is_selected == Some(true) {
+                tracing::warn!(
+                    "The {} tool is disabled by the tool policy",
+                    &definition.name
+                );
+            } else {
+                tracing::info!(
+                    "The {} tool is disabled by the tool policy",
+                    &definition.name
+                );
+            }
+            return false;
+        }
+        if let Err(failures) = tool.get_prequisites().is_satisfied() {
+            let message = failures
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if is_selected == Some(true) {
+                tracing::warn!("The {} tool is unavailable: {message}", &definition.name);
+            } else {
+                tracing::info!("The {} tool is unavailable: {message}", &definition.name);
+            }
+            return false;
+        }
+        true
+    });
+    Ok(registry)
+}
+
 async fn actual_main<C: LlmClient>(
     client: C,
     config: &productivity_config::Config,
     ask: Ask,
 ) -> anyhow::Result<()> {
-    let mut tool_map = std::collections::HashMap::<String, Arc<dyn tools::Tool>>::new();
-    let mut tool_definitions = vec![];
-    for tool in tools::rust_tools::get_rust_tools() {
-        let definition = tool.get_definition();
-        tool_map.insert(definition.name.to_string(), tool);
-        tool_definitions.push(definition);
-    }
+    let audit_log = audit::AuditLog::new(config);
+    let redactor = redaction::Redactor::new(&config.redaction)?;
+    let tool_registry = get_tools(config, &ask)?;
 
-    let mut original_query = C::Query::create_query(get_system_prompt(config));
-    original_query.add_question(ask.question.join(" "));
+    let question = ask.question.join(" ");
+    let mut transcript = export::Transcript::default();
+    let mut code_blocks = vec![];
+    transcript.add_question(&question);
+    let mut original_query = C::Query::create_query(get_system_prompt(config), &tool_registry);
+    original_query.add_question(question);
 
     let mut new_message = true;
-    let printer = response_parsing::Printer::new();
+    let mut printer = response_parsing::Printer::new(
+        color_mode::load_theme(config.ask_theme.as_deref())?,
+        color_mode::ColorMode::detect(),
+    );
 
     while new_message {
         new_message = false;
 
-        let (response, mut new_query) = client.query(original_query.clone()).await?;
+        status::start("waiting for model");
+        let query_result = client.query(original_query.clone()).await;
+        status::clear();
+        let (response, mut new_query) = query_result?;
 
         // Print the communication
         for content in &response.text {
             printer.print(content);
         }
+        code_blocks.extend(extract::get_code_blocks(&response.text));
+        transcript.add_answer(&response.raw_text);
 
         // If tool use is requested then run the tools and send a new message
         if !response.tool_invocations.is_empty() {
@@ -150,15 +395,43 @@ async fn actual_main<C: LlmClient>(
                     }
                 }
 
-                let tool = tool_map
-                    .get(&invocation.name)
-                    .context("Tool not found")?
-                    .clone();
-                let tool_response = if let Err(message) = tool.get_prequisites().is_satisfied() {
-                    format!("Could not run {}:\n{message}", &invocation.name)
-                } else {
-                    tool.run(invocation.input).await?
-                };
+                let pending_audit = audit_log.start(&invocation.name, &invocation.input, &redactor);
+                let input = invocation.input.clone();
+                status::start_tool(&invocation.name, &input);
+                let (audit_status, result) =
+                    run_tool_invocation(&tool_registry, config, &invocation.name, invocation.input)
+                        .await;
+                status::clear();
+                let (tool_response, redaction_count) = redactor.redact(&match &result {
+                    Ok(tool_response) => tool_response.clone(),
+                    Err(e) => e.to_string(),
+                });
+                // Secrets are redacted before truncating since the truncation marker could split a secret so that it's
+                // no longer recognized
+                let tool_response = truncation::truncate_middle(
+                    &tool_response,
+                    config.tool_output_limits.get_max_bytes(&invocation.name),
+                    &tool_registry
+                        .get(&invocation.name)
+                        .map(|tool| tool.get_truncation_hint())
+                        .unwrap_or_default(),
+                );
+                // A broken audit log shouldn't stop the user from getting an answer
+                if let Err(e) = audit_log.finish(pending_audit, audit_status, &tool_response) {
+                    tracing::warn!("Failed to record the tool invocation in the audit log: {e:#}");
+                }
+                if redaction_count > 0 {
+                    println!(
+                        "{}",
+                        console::style(format!(
+                            "Redacted {redaction_count} potential secret(s) from the output of {}",
+                            &invocation.name
+                        ))
+                        .yellow()
+                    );
+                }
+                result?;
+                transcript.add_tool_call(&invocation.name, &input, &tool_response);
                 tool_pairs.push((invocation.id, tool_response));
             }
 
@@ -171,6 +444,14 @@ async fn actual_main<C: LlmClient>(
         original_query = new_query;
     }
 
+    if let Some(output) = &ask.output {
+        transcript.export(output, ask.transcript, &printer)?;
+    }
+
+    if ask.extract {
+        extract::extract(&code_blocks, &printer)?;
+    }
+
     Ok(())
 }
 
@@ -199,12 +480,48 @@ fn set_up_tracing(verbose: bool) {
 fn main() -> anyhow::Result<()> {
     let ask: Ask = argh::from_env();
     set_up_tracing(ask.verbose);
+    console::set_colors_enabled(color_mode::ColorMode::detect() != color_mode::ColorMode::Plain);
 
     if ask.question.is_empty() {
         tracing::error!("No question provided");
         std::process::exit(1);
     }
 
+    let args: Vec<String> = std::env::args().collect();
+    let command = if is_after_separator(&args, &ask.question) {
+        None
+    } else {
+        match parse_command(&ask.question) {
+            Ok(command) => command,
+            Err(early_exit) if early_exit.status.is_ok() => {
+                println!("{}", early_exit.output);
+                std::process::exit(0);
+            }
+            Err(early_exit) => {
+                eprintln!("{}", early_exit.output);
+                std::process::exit(1);
+            }
+        }
+    };
+    if let Some(command) = command {
+        // The doctor reads the config itself so it can report problems with it
+        let result = if let Command::Doctor(doctor_command) = command {
+            doctor_command.run()
+        } else {
+            let config = productivity_config::Config::get_or_default().context("Reading config")?;
+            match command {
+                Command::Audit(audit_command) => audit_command.run(&config),
+                Command::Models(models_command) => models_command.run(&config),
+                Command::Doctor(_) => unreachable!(),
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
@@ -212,13 +529,11 @@ fn main() -> anyhow::Result<()> {
 
     let config = productivity_config::Config::get_or_default().context("Reading config")?;
     let result = match &config.llm_provider {
-        productivity_config::LlmProvider::Ollama { model, .. } => {
-            let ollama_client = OllamaClient {
-                base_url: config.llm_provider.get_url_base().to_string(),
-                model: model.clone().unwrap_or_else(|| String::from("llama3.1:8b")),
-            };
-            runtime.block_on(actual_main(ollama_client, &config, ask))
-        }
+        productivity_config::LlmProvider::Ollama { .. } => runtime.block_on(actual_main(
+            OllamaClient::from_config(&config)?,
+            &config,
+            ask,
+        )),
         productivity_config::LlmProvider::Anthropic { api_key } => {
             if api_key.is_empty() {
                 anyhow::bail!(
@@ -247,3 +562,192 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Convert string slices to owned strings.
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    /// Run a tool invocation with a tool policy rule for the tool.
+    fn run_with_rule(
+        name: &str,
+        input: serde_json::Value,
+        rule: productivity_config::ToolRule,
+    ) -> (audit::AuditStatus, String) {
+        let mut config = productivity_config::Config::default();
+        config.tool_policy.rules.insert(name.to_string(), rule);
+        let registry = tools::ToolRegistry::from_tools(tools::rust_tools::get_rust_tools());
+        let (status, result) = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(run_tool_invocation(&registry, &config, name, input));
+        (status, result.unwrap())
+    }
+
+    #[test]
+    fn denies_invocations_of_tools_which_cannot_enforce_their_rule() {
+        let (status, result) = run_with_rule(
+            "package_manager",
+            serde_json::json!({ "packages_to_install": ["jq"] }),
+            productivity_config::ToolRule {
+                allowed_paths: vec!["/src/**".to_string()],
+                ..productivity_config::ToolRule::default()
+            },
+        );
+        assert_eq!(status, audit::AuditStatus::Denied);
+        assert_eq!(
+            result,
+            "The user's tool policy does not allow this invocation of package_manager:\nThe tool can't enforce the allowed_paths of its tool policy rule, so it can't be used until they're removed from the rule"
+        );
+
+        let (status, result) = run_with_rule(
+            "open",
+            serde_json::json!({ "path": "/etc/passwd", "is_source_code": false }),
+            productivity_config::ToolRule {
+                allowed_paths: vec!["/src/**".to_string()],
+                ..productivity_config::ToolRule::default()
+            },
+        );
+        assert_eq!(status, audit::AuditStatus::Denied);
+        assert!(result.contains("/etc/passwd is not allowed by the tool policy"));
+    }
+
+    /// Get the names of the tools offered for some arguments.
+    fn get_tool_names(
+        config: &productivity_config::Config,
+        arguments: &[&str],
+    ) -> anyhow::Result<Vec<String>> {
+        let ask = Ask::from_args(&["ask"], arguments).map_err(|e| anyhow::anyhow!(e.output))?;
+        let mut names: Vec<String> = get_tools(config, &ask)?
+            .definitions()
+            .map(|definition| definition.name.clone())
+            .collect();
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    #[test]
+    fn parses_tool_names() {
+        assert_eq!(
+            parse_tool_names(" read_files,,open ").unwrap(),
+            ["read_files", "open"]
+        );
+        assert!(parse_tool_names(" , ").is_err());
+    }
+
+    #[test]
+    fn selects_tools() {
+        let mut config = productivity_config::Config::default();
+        config
+            .mcp_servers
+            .push(productivity_config::McpServerConfig {
+                name: "broken".to_string(),
+                command: "/nonexistent/ask-test-mcp-server".to_string(),
+                args: vec![],
+                env: std::collections::HashMap::new(),
+            });
+
+        assert_eq!(
+            get_tool_names(&config, &["--tools", "read_files,open", "question"]).unwrap(),
+            ["open", "read_files"]
+        );
+        assert!(get_tool_names(&config, &["--no-tools", "question"])
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            get_tool_names(
+                &config,
+                &["--tools", "read_files", "--no-tools", "question"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "--tools and --no-tools can't be used together"
+        );
+        assert!(get_tool_names(&config, &["--tools", ",", "question"]).is_err());
+
+        let unknown = get_tool_names(&config, &["--tools", "read_files,nope", "question"])
+            .unwrap_err()
+            .to_string();
+        assert!(unknown.starts_with("Unknown tool(s) nope, the tools are: "));
+        assert!(unknown.contains("broken_*"));
+        // The tools of an MCP server which fails to start are unavailable rather than unknown
+        assert_eq!(
+            get_tool_names(&config, &["--tools", "read_files,broken_echo", "question"]).unwrap(),
+            ["read_files"]
+        );
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(
+            parse_command(&strings(&["doctor"])),
+            Ok(Some(Command::Doctor(_)))
+        ));
+        assert!(matches!(
+            parse_command(&strings(&["audit", "--tool", "read_files"])),
+            Ok(Some(Command::Audit(_)))
+        ));
+        assert!(matches!(
+            parse_command(&strings(&["models", "list"])),
+            Ok(Some(Command::Models(_)))
+        ));
+        assert!(
+            parse_command(&strings(&["models", "--help"])).is_err_and(|help| help.status.is_ok())
+        );
+    }
+
+    #[test]
+    fn reports_invalid_command_arguments() {
+        let error = |question: &[&str]| match parse_command(&strings(question)) {
+            Err(early_exit) if early_exit.status.is_err() => early_exit.output,
+            _ => panic!("{question:?} wasn't reported as invalid"),
+        };
+        assert!(error(&["models", "rm"]).contains("Required positional arguments not provided"));
+        assert_eq!(
+            error(&["audit", "--limt", "5"]),
+            "Unrecognized argument: --limt\n"
+        );
+        assert_eq!(
+            error(&["doctor", "why", "-v"]),
+            "Unrecognized argument: why\n"
+        );
+        assert_eq!(
+            error(&["models", "pull", "a", "b"]),
+            "Unrecognized argument: b\n"
+        );
+    }
+
+    #[test]
+    fn asks_questions_which_start_with_command_names() {
+        for question in [
+            &["doctor", "who", "is", "the", "best?"][..],
+            &["audit", "my", "dependencies"],
+            &["models", "of", "cars"],
+            &["what", "is", "doctor"],
+        ] {
+            assert!(matches!(parse_command(&strings(question)), Ok(None)));
+        }
+    }
+
+    #[test]
+    fn never_parses_commands_after_a_separator() {
+        let question = strings(&["doctor"]);
+        assert!(is_after_separator(
+            &strings(&["ask", "--", "doctor"]),
+            &question
+        ));
+        assert!(is_after_separator(
+            &strings(&["ask", "-v", "--", "doctor"]),
+            &question
+        ));
+        assert!(!is_after_separator(&strings(&["ask", "doctor"]), &question));
+        assert!(!is_after_separator(
+            &strings(&["ask", "doctor", "--"]),
+            &question
+        ));
+    }
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 /// Details about a model.
 #[derive(serde::Deserialize, Debug)]
 #[allow(dead_code)]
@@ -46,10 +44,45 @@ pub struct PullRequest {
     pub stream: bool,
 }
 
-/// Request for the `/api/pull` endpoint.
+/// A progress update streamed from the `/api/pull` endpoint.
 #[derive(serde::Deserialize, Debug)]
-pub struct PullResponse {
+pub struct PullProgress {
+    /// What the pull is doing, "success" once it's complete.
+    #[serde(default)]
     pub status: String,
+    /// The digest of the layer being downloaded.
+    pub digest: Option<String>,
+    /// The size of the layer being downloaded in bytes.
+    pub total: Option<u64>,
+    /// The number of bytes of the layer downloaded so far.
+    pub completed: Option<u64>,
+    /// The error which stopped the pull.
+    pub error: Option<String>,
+}
+
+/// Request for the `/api/delete` and `/api/show` endpoints.
+#[derive(serde::Serialize, Debug)]
+pub struct ModelRequest {
+    pub model: String,
+}
+
+/// The response from the `/api/show` endpoint.
+#[derive(serde::Deserialize, Debug)]
+pub struct ShowResponse {
+    /// The model's parameters, one per line.
+    #[serde(default)]
+    pub parameters: String,
+    /// The prompt template.
+    #[serde(default)]
+    pub template: String,
+    /// The model's license.
+    #[serde(default)]
+    pub license: String,
+    /// Details about the model.
+    pub details: ModelDetails,
+    /// Information about the model's architecture, such as its context length.
+    #[serde(default)]
+    pub model_info: serde_json::Map<String, serde_json::Value>,
 }
 
 /// A tool call.
@@ -117,9 +150,33 @@ pub struct ChatRequest {
     /// The chat messages.
     pub messages: Vec<ChatMessage>,
     /// The tools to make available.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<OllamaTool>,
     /// Whether or not to stream the response.
     pub stream: bool,
+    /// The model options.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<ChatOptions>,
+    /// How long to keep the model loaded after the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    /// The format of the response, either `json` or a JSON schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<serde_json::Value>,
+}
+
+/// Options for the model which is run.
+#[derive(serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ChatOptions {
+    /// The size of the context window in tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// The sampling temperature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// The random seed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
 }
 
 /// Request for the `/api/chat` endpoint.
@@ -147,44 +204,17 @@ pub struct ChatResponse {
 }
 
 impl crate::llm_client::LlmQuery for ChatRequest {
-    fn create_query(system_prompt: String) -> Self {
-        let mut tools = vec![];
-        for tool in crate::tools::rust_tools::get_rust_tools() {
-            let definition = tool.get_definition();
-            let mut properties = HashMap::with_capacity(1);
-            for (key, value) in definition.input_schema["properties"].as_object().unwrap() {
-                let json_type = &value["type"];
-                let type_string = if let Some(array) = json_type.as_array() {
-                    array
-                        .iter()
-                        .filter_map(|x| x.as_str())
-                        .find(|&x| x != "null")
-                        .unwrap()
-                } else {
-                    json_type.as_str().unwrap()
-                };
-                properties.insert(
-                    key,
-                    serde_json::json!({
-                        "type": type_string,
-                        "description": value["description"],
-                        "enum": value["enum"],
-                    }),
-                );
-            }
-
-            let parameters = serde_json::json!({
-                "type": definition.input_schema["type"],
-                "required": definition.input_schema["required"],
-                "properties": properties,
-            });
-
-            tools.push(OllamaTool::from(ToolDefinition::Function {
-                name: definition.name,
-                description: definition.description,
-                parameters,
-            }));
-        }
+    fn create_query(system_prompt: String, tools: &crate::tools::ToolRegistry) -> Self {
+        let tools = tools
+            .definitions()
+            .map(|definition| {
+                OllamaTool::from(ToolDefinition::Function {
+                    name: definition.name.clone(),
+                    description: definition.description.clone(),
+                    parameters: super::schema::to_ollama_schema(&definition.input_schema),
+                })
+            })
+            .collect();
 
         let mut messages = Vec::with_capacity(2);
         messages.push(ChatMessage {
@@ -199,6 +229,9 @@ impl crate::llm_client::LlmQuery for ChatRequest {
             messages,
             tools,
             stream: false,
+            options: None,
+            keep_alive: None,
+            format: None,
         }
     }
 
@@ -1,25 +1,148 @@
 //! Ollama-related code.
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use anyhow::Context;
 use data_types::{ChatResponse, ToolCall};
 
 use crate::llm_client::{LlmResponse, ToolInvocation};
 
 mod data_types;
+pub mod models;
+mod schema;
 
-/// A basic client for the Anthropic API.
+/// A basic client for the Ollama API.
 pub struct OllamaClient {
     /// The base URL for the API.
     pub base_url: String,
     /// The model to use.
     pub model: String,
+    /// Whether the model has been checked to be downloaded this session.
+    model_checked: AtomicBool,
+    /// The HTTP client, with any authentication headers set.
+    client: reqwest::Client,
+    /// The model options sent with every chat request.
+    options: Option<data_types::ChatOptions>,
+    /// How long to keep the model loaded after a request.
+    keep_alive: Option<String>,
+    /// The format of the response.
+    format: Option<serde_json::Value>,
+}
+
+/// Splits the newline delimited JSON progress of a pull into updates, however the response is chunked.
+#[derive(Default)]
+struct PullProgressParser {
+    /// The start of a line which hasn't been received in full.
+    buffer: Vec<u8>,
+    /// The status of the last update.
+    status: String,
+}
+
+impl PullProgressParser {
+    /// Parse a line of progress, failing with the error the server reported.
+    fn parse_line(&mut self, line: &[u8]) -> anyhow::Result<Option<data_types::PullProgress>> {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        let update: data_types::PullProgress =
+            serde_json::from_slice(line).context("Parsing pull progress")?;
+        if let Some(error) = update.error {
+            anyhow::bail!("{error}");
+        }
+        self.status.clone_from(&update.status);
+        Ok(Some(update))
+    }
+
+    /// Add a chunk of the response, returning the updates on the lines it completes.
+    fn push(&mut self, chunk: &[u8]) -> anyhow::Result<Vec<data_types::PullProgress>> {
+        self.buffer.extend_from_slice(chunk);
+        let mut updates = vec![];
+        while let Some(newline) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            updates.extend(self.parse_line(&line)?);
+        }
+        Ok(updates)
+    }
+
+    /// Check the pull succeeded once the response has ended.
+    fn finish(mut self) -> anyhow::Result<()> {
+        let line = std::mem::take(&mut self.buffer);
+        self.parse_line(&line)?;
+        if self.status != "success" {
+            anyhow::bail!("The last status was {:?}", self.status);
+        }
+        Ok(())
+    }
 }
 
 impl OllamaClient {
+    /// Create a client for the Ollama provider in the config.
+    pub fn from_config(config: &productivity_config::Config) -> anyhow::Result<Self> {
+        let productivity_config::LlmProvider::Ollama {
+            model,
+            num_ctx,
+            temperature,
+            seed,
+            keep_alive,
+            format,
+            headers,
+            bearer_token,
+            ..
+        } = &config.llm_provider
+        else {
+            anyhow::bail!(
+                "The configured LLM provider is not Ollama - configure it in {}",
+                config
+                    .config_file_path
+                    .as_deref()
+                    .unwrap_or("the config file")
+            );
+        };
+
+        let mut header_map = reqwest::header::HeaderMap::with_capacity(headers.len() + 1);
+        for (name, value) in headers {
+            header_map.insert(
+                reqwest::header::HeaderName::try_from(name.as_str())
+                    .with_context(|| format!("Parsing the Ollama header name {name}"))?,
+                reqwest::header::HeaderValue::try_from(value.as_str())
+                    .with_context(|| format!("Parsing the value of the Ollama header {name}"))?,
+            );
+        }
+        if let Some(bearer_token) = bearer_token {
+            let mut value =
+                reqwest::header::HeaderValue::try_from(format!("Bearer {bearer_token}"))
+                    .context("Parsing the Ollama bearer token")?;
+            value.set_sensitive(true);
+            header_map.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(header_map)
+            .build()
+            .context("Creating the Ollama HTTP client")?;
+
+        let options = data_types::ChatOptions {
+            num_ctx: *num_ctx,
+            temperature: *temperature,
+            seed: *seed,
+        };
+        Ok(Self {
+            base_url: config.llm_provider.get_url_base().to_string(),
+            model: model.clone().unwrap_or_else(|| String::from("llama3.1:8b")),
+            model_checked: AtomicBool::new(false),
+            client,
+            options: (options != data_types::ChatOptions::default()).then_some(options),
+            keep_alive: keep_alive.clone(),
+            format: format.clone(),
+        })
+    }
+
     /// Get the currently downloaded models.
     pub async fn get_tags(&self) -> anyhow::Result<data_types::TagsResponse> {
         let url = format!("{}/api/tags", self.base_url);
-        let response = reqwest::get(&url)
+        let response = self
+            .client
+            .get(&url)
+            .send()
             .await?
             .error_for_status()?
             .json::<data_types::TagsResponse>()
@@ -27,27 +150,56 @@ impl OllamaClient {
         Ok(response)
     }
 
-    /// Pull a model.
-    pub async fn pull(&self, name: &str) -> anyhow::Result<data_types::PullResponse> {
+    /// Pull a model, showing the download progress.
+    pub async fn pull(&self, name: &str) -> anyhow::Result<()> {
         let url = format!("{}/api/pull", self.base_url);
         let request = data_types::PullRequest {
             model: name.to_string(),
             insecure: false,
-            stream: false,
+            stream: true,
         };
-        let response = reqwest::Client::new()
+        let mut response = self
+            .client
             .post(&url)
             .json(&request)
             .send()
             .await?
-            .error_for_status()?
-            .json::<data_types::PullResponse>()
-            .await?;
+            .error_for_status()?;
 
-        if &response.status != "success" {
-            anyhow::bail!("Failed to pull model: {:?}", response);
+        crate::status::clear();
+        let progress = indicatif::ProgressBar::new(0).with_style(
+            indicatif::ProgressStyle::with_template(
+                "{msg} {wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .unwrap(),
+        );
+        let mut parser = PullProgressParser::default();
+        let mut status = String::new();
+        while let Some(chunk) = response.chunk().await.context("Reading pull progress")? {
+            let updates = match parser.push(&chunk) {
+                Ok(updates) => updates,
+                Err(e) => {
+                    progress.abandon();
+                    return Err(e).with_context(|| format!("Failed to pull model {name}"));
+                }
+            };
+            for update in updates {
+                if let (Some(total), Some(digest)) = (update.total, &update.digest) {
+                    let digest = digest.trim_start_matches("sha256:");
+                    progress.set_length(total);
+                    progress.set_position(update.completed.unwrap_or(0));
+                    progress.set_message(format!("{} {}", name, &digest[..digest.len().min(12)]));
+                } else if update.status != status {
+                    progress.set_message(format!("{name} {}", update.status));
+                }
+                status = update.status;
+            }
         }
-        Ok(response)
+        progress.finish_and_clear();
+
+        parser
+            .finish()
+            .with_context(|| format!("Failed to pull model {name}"))
     }
 
     /// Pull a model if it's not already downloaded.
@@ -60,6 +212,39 @@ impl OllamaClient {
         }
         Ok(())
     }
+
+    /// Delete a downloaded model.
+    pub async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        let url = format!("{}/api/delete", self.base_url);
+        self.client
+            .delete(&url)
+            .json(&data_types::ModelRequest {
+                model: name.to_string(),
+            })
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Deleting model {name}"))?;
+        Ok(())
+    }
+
+    /// Get information about a downloaded model.
+    pub async fn show(&self, name: &str) -> anyhow::Result<data_types::ShowResponse> {
+        let url = format!("{}/api/show", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&data_types::ModelRequest {
+                model: name.to_string(),
+            })
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Getting information about model {name}"))?
+            .json::<data_types::ShowResponse>()
+            .await?;
+        Ok(response)
+    }
 }
 
 /// Convert an Ollama response to the internal response representation.
@@ -83,6 +268,7 @@ fn ollama_to_internal(response: &ChatResponse) -> anyhow::Result<LlmResponse> {
 
     Ok(LlmResponse {
         text: text_blocks,
+        raw_text: response.message.content.clone(),
         tool_invocations,
     })
 }
@@ -92,7 +278,11 @@ impl crate::llm_client::LlmClient for OllamaClient {
 
     /// Query the Anthropic API.
     async fn query(&self, mut query: Self::Query) -> anyhow::Result<(LlmResponse, Self::Query)> {
-        self.pull_if_needed(&self.model).await?;
+        // Only check the model is downloaded once per session
+        if !self.model_checked.load(Ordering::Relaxed) {
+            self.pull_if_needed(&self.model).await?;
+            self.model_checked.store(true, Ordering::Relaxed);
+        }
 
         if tracing::enabled!(tracing::Level::INFO) {
             if let Ok(serialized_query) =
@@ -103,9 +293,13 @@ impl crate::llm_client::LlmClient for OllamaClient {
         }
 
         query.model.clone_from(&self.model);
+        query.options.clone_from(&self.options);
+        query.keep_alive.clone_from(&self.keep_alive);
+        query.format.clone_from(&self.format);
 
         let url = format!("{}/api/chat", self.base_url);
-        let response = reqwest::Client::new()
+        let response = self
+            .client
             .post(&url)
             .json(&query)
             .send()
@@ -127,3 +321,55 @@ impl crate::llm_client::LlmClient for OllamaClient {
         Ok((ollama_to_internal(&response)?, query))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse pull progress split into chunks, returning the statuses of the updates and how the pull finished.
+    fn parse(chunks: &[&str]) -> (Vec<String>, Result<(), String>) {
+        let mut parser = PullProgressParser::default();
+        let mut statuses = vec![];
+        for chunk in chunks {
+            match parser.push(chunk.as_bytes()) {
+                Ok(updates) => statuses.extend(updates.into_iter().map(|update| update.status)),
+                Err(e) => return (statuses, Err(format!("{e:#}"))),
+            }
+        }
+        (statuses, parser.finish().map_err(|e| format!("{e:#}")))
+    }
+
+    #[test]
+    fn parses_pull_progress_split_across_chunks() {
+        let (statuses, result) = parse(&[
+            r#"{"status":"pulling manifest"}"#,
+            "\n\n{\"status\":\"downloading\",\"digest\":\"sha256:ab",
+            r#"cd","total":10,"completed":5}"#,
+            "\n{\"status\":\"success\"}",
+        ]);
+        assert_eq!(statuses, ["pulling manifest", "downloading"]);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn reports_pull_errors() {
+        let (statuses, result) = parse(&[
+            "{\"status\":\"pulling manifest\"}\n{\"error\":\"pull model manifest: file does not exist\"}\n",
+        ]);
+        assert_eq!(statuses, Vec::<String>::new());
+        assert_eq!(
+            result,
+            Err("pull model manifest: file does not exist".to_string())
+        );
+
+        let (statuses, result) = parse(&["{\"status\":\"verifying sha256 digest\"}\n"]);
+        assert_eq!(statuses, ["verifying sha256 digest"]);
+        assert_eq!(
+            result,
+            Err("The last status was \"verifying sha256 digest\"".to_string())
+        );
+
+        let (_, result) = parse(&["not json\n"]);
+        assert!(result.unwrap_err().starts_with("Parsing pull progress"));
+    }
+}
@@ -0,0 +1,172 @@
+//! The `ask models` command for managing Ollama models.
+
+use anyhow::Context;
+use argh::FromArgs;
+
+use super::OllamaClient;
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "models")]
+/// Manage the models of the Ollama provider.
+pub struct ModelsCommand {
+    #[argh(subcommand)]
+    command: ModelsSubcommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+/// The model management commands.
+enum ModelsSubcommand {
+    List(ListCommand),
+    Pull(PullCommand),
+    Rm(RmCommand),
+    Show(ShowCommand),
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "list")]
+/// List the downloaded models.
+struct ListCommand {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "pull")]
+/// Download a model, or the configured model if none is given.
+struct PullCommand {
+    #[argh(positional)]
+    /// the model to download
+    model: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "rm")]
+/// Delete a downloaded model.
+struct RmCommand {
+    #[argh(positional)]
+    /// the model to delete
+    model: String,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "show")]
+/// Show information about a model, or the configured model if none is given.
+struct ShowCommand {
+    #[argh(positional)]
+    /// the model to show
+    model: Option<String>,
+}
+
+impl ModelsCommand {
+    /// Run the model management command.
+    pub fn run(&self, config: &productivity_config::Config) -> anyhow::Result<()> {
+        let client = OllamaClient::from_config(config)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Creating the async runtime")?;
+        runtime.block_on(async {
+            match &self.command {
+                ModelsSubcommand::List(_) => list(&client).await,
+                ModelsSubcommand::Pull(pull) => {
+                    let model = pull.model.as_ref().unwrap_or(&client.model);
+                    client.pull(model).await?;
+                    println!("Model {model} downloaded.");
+                    Ok(())
+                }
+                ModelsSubcommand::Rm(rm) => {
+                    client.delete(&rm.model).await?;
+                    println!("Model {} deleted.", rm.model);
+                    Ok(())
+                }
+                ModelsSubcommand::Show(show_command) => {
+                    show(
+                        &client,
+                        show_command.model.as_ref().unwrap_or(&client.model),
+                    )
+                    .await
+                }
+            }
+        })
+    }
+}
+
+/// Print a table of the downloaded models.
+async fn list(client: &OllamaClient) -> anyhow::Result<()> {
+    let tags = client.get_tags().await?;
+    if tags.models.is_empty() {
+        println!("No models are downloaded, use `ask models pull` to download one.");
+        return Ok(());
+    }
+
+    let mut rows = vec![vec![
+        "Name".to_string(),
+        "Size".to_string(),
+        "Parameters".to_string(),
+        "Quantization".to_string(),
+        "Modified".to_string(),
+    ]];
+    for model in tags.models {
+        let modified = chrono::DateTime::parse_from_rfc3339(&model.modified_at).map_or_else(
+            |_| model.modified_at.clone(),
+            |modified| modified.format("%Y-%m-%d %H:%M").to_string(),
+        );
+        let name = if model.name == client.model {
+            format!("{} (configured)", model.name)
+        } else {
+            model.name
+        };
+        rows.push(vec![
+            name,
+            indicatif::HumanBytes(u64::try_from(model.size).unwrap_or(0)).to_string(),
+            model.details.parameter_size,
+            model.details.quantization_level,
+            modified,
+        ]);
+    }
+
+    let alignments = [
+        pulldown_cmark::Alignment::Left,
+        pulldown_cmark::Alignment::Right,
+        pulldown_cmark::Alignment::Right,
+        pulldown_cmark::Alignment::Left,
+        pulldown_cmark::Alignment::Left,
+    ];
+    for line in crate::response_parsing::render_table(&alignments, &rows) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Print information about a model.
+async fn show(client: &OllamaClient, model: &str) -> anyhow::Result<()> {
+    let information = client.show(model).await?;
+    println!("{}", console::style(model).bold());
+    println!("  family: {}", information.details.family);
+    println!("  format: {}", information.details.format);
+    println!("  parameters: {}", information.details.parameter_size);
+    println!("  quantization: {}", information.details.quantization_level);
+    if let Some(context_length) = information
+        .model_info
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .map(|(_, value)| value)
+    {
+        println!("  context length: {context_length}");
+    }
+
+    if !information.parameters.is_empty() {
+        println!("{}", console::style("Parameters").bold());
+        for line in information.parameters.lines() {
+            println!("  {line}");
+        }
+    }
+    if !information.template.is_empty() {
+        println!("{}", console::style("Template").bold());
+        for line in information.template.lines() {
+            println!("  {line}");
+        }
+    }
+    if let Some(license) = information.license.lines().next() {
+        println!("{} {license}", console::style("License").bold());
+    }
+    Ok(())
+}
@@ -0,0 +1,303 @@
+//! Conversion of the JSON schemas generated by `schemars` to the schemas Ollama expects for tool parameters.
+
+use serde_json::{Map, Value};
+
+/// Keywords whose values are a single subschema.
+const SUBSCHEMA_KEYWORDS: &[&str] = &["items", "additionalProperties", "not"];
+
+/// Keywords whose values are arrays of subschemas.
+const SUBSCHEMA_ARRAY_KEYWORDS: &[&str] = &["oneOf", "anyOf", "allOf"];
+
+/// Keywords which only make sense at the root of a standalone schema document.
+const DOCUMENT_KEYWORDS: &[&str] = &["$schema", "definitions", "$defs", "title"];
+
+/// Convert a tool's input schema to the parameters of an Ollama tool definition.
+///
+/// References are inlined since Ollama doesn't resolve `$ref`, and the root is always an object with `properties` and `required`.
+pub fn to_ollama_schema(schema: &Value) -> Value {
+    let definitions = schema
+        .get("definitions")
+        .or_else(|| schema.get("$defs"))
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut converted = convert(schema, &definitions, &mut vec![]);
+    if let Value::Object(root) = &mut converted {
+        root.insert("type".to_string(), Value::String("object".to_string()));
+        root.entry("properties")
+            .or_insert_with(|| Value::Object(Map::new()));
+        root.entry("required")
+            .or_insert_with(|| Value::Array(vec![]));
+    }
+    converted
+}
+
+/// Convert a subschema, inlining references.
+///
+/// `resolving` holds the references currently being inlined so recursive types terminate.
+fn convert(schema: &Value, definitions: &Map<String, Value>, resolving: &mut Vec<String>) -> Value {
+    let Value::Object(schema) = schema else {
+        // `true` allows anything and `false` allows nothing, Ollama understands neither
+        return match schema {
+            Value::Bool(false) => Value::Object(Map::from_iter([(
+                "not".to_string(),
+                Value::Object(Map::new()),
+            )])),
+            _ => Value::Object(Map::new()),
+        };
+    };
+
+    let mut converted = Map::with_capacity(schema.len());
+
+    // Inline references, keeping any sibling keywords such as the description
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference
+            .strip_prefix("#/definitions/")
+            .or_else(|| reference.strip_prefix("#/$defs/"));
+        match name.and_then(|name| Some((name, definitions.get(name)?))) {
+            Some((name, _)) if resolving.iter().any(|resolving| resolving == name) => {
+                // A recursive type, stop inlining here
+                converted.insert("type".to_string(), Value::String("object".to_string()));
+            }
+            Some((name, definition)) => {
+                resolving.push(name.to_string());
+                if let Value::Object(definition) = convert(definition, definitions, resolving) {
+                    converted.extend(definition);
+                }
+                resolving.pop();
+            }
+            None => tracing::warn!("Could not resolve the schema reference {reference}"),
+        }
+    }
+
+    for (key, value) in schema {
+        let key = key.as_str();
+        if key == "$ref" || DOCUMENT_KEYWORDS.contains(&key) {
+            continue;
+        }
+
+        let value = if key == "properties" {
+            match value {
+                Value::Object(properties) => Value::Object(
+                    properties
+                        .iter()
+                        .map(|(name, property)| {
+                            (name.clone(), convert(property, definitions, resolving))
+                        })
+                        .collect(),
+                ),
+                value => value.clone(),
+            }
+        } else if SUBSCHEMA_KEYWORDS.contains(&key) {
+            match value {
+                // `additionalProperties: false` is meaningful, keep it
+                Value::Bool(_) => value.clone(),
+                // Tuple validation
+                Value::Array(items) => Value::Array(
+                    items
+                        .iter()
+                        .map(|item| convert(item, definitions, resolving))
+                        .collect(),
+                ),
+                value => convert(value, definitions, resolving),
+            }
+        } else if SUBSCHEMA_ARRAY_KEYWORDS.contains(&key) {
+            match value {
+                Value::Array(subschemas) => Value::Array(
+                    subschemas
+                        .iter()
+                        .map(|subschema| convert(subschema, definitions, resolving))
+                        .collect(),
+                ),
+                value => value.clone(),
+            }
+        } else if key == "type" {
+            // Optional fields are typed as `[T, "null"]`, Ollama expects a single type and optionality is
+            // already expressed by the field not being required
+            match value {
+                Value::Array(types) => {
+                    let non_null: Vec<&Value> = types
+                        .iter()
+                        .filter(|json_type| json_type.as_str() != Some("null"))
+                        .collect();
+                    match non_null.as_slice() {
+                        [json_type] => (*json_type).clone(),
+                        _ => value.clone(),
+                    }
+                }
+                value => value.clone(),
+            }
+        } else {
+            value.clone()
+        };
+        converted.insert(key.to_string(), value);
+    }
+
+    // `schemars` wraps references with a description in a single element `allOf`, merge it into this schema
+    if let Some(Value::Array(all_of)) = converted.get("allOf") {
+        if let [Value::Object(only)] = all_of.as_slice() {
+            let only = only.clone();
+            converted.remove("allOf");
+            for (key, value) in only {
+                converted.entry(key).or_insert(value);
+            }
+        }
+    }
+
+    Value::Object(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that a converted schema has no references left in it.
+    fn assert_no_references(schema: &Value) {
+        match schema {
+            Value::Object(object) => {
+                assert!(
+                    !object.contains_key("$ref"),
+                    "unresolved reference in {schema}"
+                );
+                assert!(!object.contains_key("definitions"));
+                object.values().for_each(assert_no_references);
+            }
+            Value::Array(array) => array.iter().for_each(assert_no_references),
+            _ => {}
+        }
+    }
+
+    /// Get the converted schema of a tool's input.
+    fn get_tool_schema(name: &str) -> Value {
+        let definition = crate::tools::rust_tools::get_rust_tools()
+            .into_iter()
+            .map(|tool| tool.get_definition())
+            .find(|definition| definition.name == name)
+            .unwrap();
+        to_ollama_schema(&definition.input_schema)
+    }
+
+    #[test]
+    fn every_tool_converts() {
+        for tool in crate::tools::rust_tools::get_rust_tools() {
+            let definition = tool.get_definition();
+            let schema = to_ollama_schema(&definition.input_schema);
+            assert_no_references(&schema);
+            assert_eq!(schema["type"], "object", "{}", definition.name);
+            assert!(schema["properties"].is_object(), "{}", definition.name);
+            assert!(schema["required"].is_array(), "{}", definition.name);
+            for (name, property) in schema["properties"].as_object().unwrap() {
+                assert!(
+                    property["type"].is_string() || property.get("oneOf").is_some(),
+                    "{}.{name} has no type: {property}",
+                    definition.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        let schema = get_tool_schema("cloud_context");
+        assert_eq!(schema["properties"], serde_json::json!({}));
+        assert_eq!(schema["required"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn maps() {
+        let schema = get_tool_schema("read_files");
+        assert_eq!(
+            schema["properties"]["paths_to_reason_mapping"]["additionalProperties"]["type"],
+            "string"
+        );
+        assert_eq!(
+            schema["properties"]["byte_offsets"]["additionalProperties"]["type"],
+            "integer"
+        );
+
+        let schema = get_tool_schema("write_files");
+        assert_eq!(
+            schema["properties"]["paths_to_content"]["additionalProperties"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        for name in ["ffmpeg", "tar", "unzip"] {
+            let schema = get_tool_schema(name);
+            assert_eq!(schema["properties"]["arguments"]["type"], "array");
+            assert_eq!(schema["properties"]["arguments"]["items"]["type"], "string");
+            assert_eq!(schema["properties"]["working_directory"]["type"], "string");
+            assert_eq!(schema["required"], serde_json::json!(["arguments"]));
+        }
+
+        let schema = get_tool_schema("package_manager");
+        assert_eq!(
+            schema["properties"]["packages_to_install"]["items"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn tagged_enum_references() {
+        let schema = get_tool_schema("software_version");
+        let variant = &schema["properties"]["libraries"]["items"]["oneOf"][0];
+        assert_eq!(variant["type"], "object");
+        assert_eq!(variant["properties"]["crate_name"]["type"], "string");
+        assert_eq!(
+            variant["properties"]["type"]["enum"],
+            serde_json::json!(["cargo"])
+        );
+    }
+
+    #[test]
+    fn scalars() {
+        let schema = get_tool_schema("open");
+        assert_eq!(schema["properties"]["path"]["type"], "string");
+        assert_eq!(schema["properties"]["is_source_code"]["type"], "boolean");
+        assert_eq!(
+            get_tool_schema("http_get_request")["properties"]["url"]["type"],
+            "string"
+        );
+        assert_eq!(
+            get_tool_schema("argocd_status")["properties"]["kubernetes_context_name"]["type"],
+            "string"
+        );
+        assert_eq!(
+            get_tool_schema("terraform_plan")["properties"]["working_directory"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn described_and_recursive_references() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "tree": {
+                    "description": "The tree.",
+                    "allOf": [{ "$ref": "#/definitions/Node" }]
+                }
+            },
+            "definitions": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "children": { "type": "array", "items": { "$ref": "#/definitions/Node" } }
+                    }
+                }
+            }
+        });
+        let converted = to_ollama_schema(&schema);
+        assert_no_references(&converted);
+        let tree = &converted["properties"]["tree"];
+        assert_eq!(tree["description"], "The tree.");
+        assert_eq!(tree["type"], "object");
+        assert_eq!(
+            tree["properties"]["children"]["items"],
+            serde_json::json!({ "type": "object" })
+        );
+    }
+}
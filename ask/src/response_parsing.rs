@@ -1,70 +1,388 @@
 //! Markdown response parsing library.
 
+use anyhow::Context;
+
+use crate::color_mode::ColorMode;
 use crate::llm_client::TextOutput;
 
-pub struct Printer {
+/// Prints parsed Markdown to the terminal, wrapping text to the terminal's width.
+pub struct Printer<W: std::io::Write = std::io::Stdout> {
+    writer: W,
     syntax_set: syntect::parsing::SyntaxSet,
     theme: syntect::highlighting::Theme,
+    color_mode: ColorMode,
+    /// A fixed width to wrap to instead of the terminal's width.
+    fixed_width: Option<usize>,
+    /// The number of block quotes the output is currently inside.
+    quote_depth: usize,
+    /// The marker widths of the list items the output is currently inside.
+    list_indents: Vec<usize>,
+    /// The marker of a list item which hasn't been printed yet.
+    pending_list_marker: Option<String>,
+    /// Whether the next output starts a new line.
+    at_line_start: bool,
+    /// The column the cursor is at.
+    column: usize,
+    /// The column the content of the current line starts at, after the gutter and indentation.
+    line_content_start: usize,
+    /// The number of code blocks printed so far, used to number them for `--extract`.
+    code_block_count: usize,
+}
+
+/// Pad text to a width according to a table column's alignment.
+fn align_cell(text: &str, width: usize, alignment: pulldown_cmark::Alignment) -> String {
+    let padding = width.saturating_sub(console::measure_text_width(text));
+    let (left, right) = match alignment {
+        pulldown_cmark::Alignment::Right => (padding, 0),
+        pulldown_cmark::Alignment::Center => (padding / 2, padding - padding / 2),
+        pulldown_cmark::Alignment::Left | pulldown_cmark::Alignment::None => (0, padding),
+    };
+    format!("{}{text}{}", " ".repeat(left), " ".repeat(right))
+}
+
+/// Render a table with aligned columns and box-drawing borders.
+///
+/// The first row is treated as the header.
+pub fn render_table(alignments: &[pulldown_cmark::Alignment], rows: &[Vec<String>]) -> Vec<String> {
+    let column_count = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![1; column_count];
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(console::measure_text_width(cell));
+        }
+    }
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        format!("{left}{}{right}", segments.join(middle))
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 3);
+    lines.push(border("┌", "┬", "┐"));
+    for (row_index, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                let cell = align_cell(
+                    row.get(column).map_or("", String::as_str),
+                    *width,
+                    alignments
+                        .get(column)
+                        .copied()
+                        .unwrap_or(pulldown_cmark::Alignment::None),
+                );
+                if row_index == 0 {
+                    console::style(cell).bold().to_string()
+                } else {
+                    cell
+                }
+            })
+            .collect();
+        lines.push(format!("│ {} │", cells.join(" │ ")));
+        if row_index == 0 && rows.len() > 1 {
+            lines.push(border("├", "┼", "┤"));
+        }
+    }
+    lines.push(border("└", "┴", "┘"));
+    lines
 }
 
 impl Printer {
-    pub fn new() -> Self {
-        let mut themes = syntect::highlighting::ThemeSet::load_defaults();
+    pub fn new(theme: syntect::highlighting::Theme, color_mode: ColorMode) -> Self {
+        Self::with_writer(std::io::stdout(), theme, color_mode, None)
+    }
+}
 
+impl<W: std::io::Write> Printer<W> {
+    /// Create a printer which writes to `writer`, wrapping to `fixed_width` if set or the terminal's width otherwise.
+    pub fn with_writer(
+        writer: W,
+        theme: syntect::highlighting::Theme,
+        color_mode: ColorMode,
+        fixed_width: Option<usize>,
+    ) -> Self {
         Printer {
+            writer,
             syntax_set: syntect::parsing::SyntaxSet::load_defaults_newlines(),
-            theme: themes.themes.remove("base16-ocean.dark").unwrap(),
+            theme,
+            color_mode,
+            fixed_width,
+            quote_depth: 0,
+            list_indents: vec![],
+            pending_list_marker: None,
+            at_line_start: true,
+            column: 0,
+            line_content_start: 0,
+            code_block_count: 0,
+        }
+    }
+
+    /// Get the width to wrap text to.
+    ///
+    /// The terminal is queried every time so resizing while output is streamed is handled.
+    fn width(&self) -> usize {
+        self.fixed_width.unwrap_or_else(|| {
+            console::Term::stdout()
+                .size_checked()
+                .map_or(usize::MAX, |s| s.1 as usize)
+        })
+    }
+
+    /// Write text without any wrapping.
+    fn write(&mut self, text: impl std::fmt::Display) {
+        // Failing to write to the terminal isn't actionable so errors are ignored
+        let _ = write!(self.writer, "{text}");
+    }
+
+    /// End the current line.
+    fn new_line(&mut self) {
+        if self.at_line_start {
+            // Keep the gutter on blank lines inside block quotes
+            self.write_gutter();
+        }
+        self.write("\n");
+        self.at_line_start = true;
+        self.column = 0;
+    }
+
+    /// Write the block quote gutter.
+    fn write_gutter(&mut self) {
+        for _ in 0..self.quote_depth {
+            self.write(format!("{} ", console::style("│").dim()));
+        }
+        self.column = self.quote_depth * 2;
+    }
+
+    /// Write the gutter and indentation if the output is at the start of a line.
+    fn start_line(&mut self) {
+        if !self.at_line_start {
+            return;
+        }
+        self.at_line_start = false;
+        self.write_gutter();
+
+        if let Some((last, parents)) = self.list_indents.split_last() {
+            let indent = 1 + parents.iter().sum::<usize>();
+            let last = *last;
+            self.write(" ".repeat(indent));
+            if let Some(marker) = self.pending_list_marker.take() {
+                self.write(marker);
+            } else {
+                self.write(" ".repeat(last));
+            }
+            self.column += indent + last;
+        }
+        self.line_content_start = self.column;
+    }
+
+    /// Write text, wrapping between words to fit the width.
+    fn write_wrapped(&mut self, text: &str, style: impl Fn(&str) -> String) {
+        for (line_index, line) in text.split('\n').enumerate() {
+            if line_index > 0 {
+                self.new_line();
+            }
+            for piece in line.split_inclusive(' ') {
+                let word = piece.trim_end_matches(' ');
+                let spaces = piece.len() - word.len();
+                self.start_line();
+                if !word.is_empty() {
+                    let word_width = console::measure_text_width(word);
+                    if self.column > self.line_content_start
+                        && self.column + word_width > self.width()
+                    {
+                        self.new_line();
+                        self.start_line();
+                    }
+                    self.write(style(word));
+                    self.column += word_width;
+                }
+                // Spaces which would overflow the line are dropped
+                if spaces > 0 && self.column + spaces <= self.width() {
+                    self.write(" ".repeat(spaces));
+                    self.column += spaces;
+                }
+            }
+        }
+    }
+
+    /// Suggest a file extension for a code block's language.
+    pub fn suggest_extension(&self, language: &str) -> String {
+        self.syntax_set
+            .find_syntax_by_token(language)
+            .and_then(|syntax| syntax.file_extensions.first())
+            .cloned()
+            .unwrap_or_else(|| "txt".to_string())
+    }
+
+    /// Syntax highlight a code block as HTML with the printer's theme.
+    pub fn highlight_html(&self, language: &str, content: &str) -> anyhow::Result<String> {
+        let syntax = self
+            .syntax_set
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        syntect::html::highlighted_html_for_string(content, &self.syntax_set, syntax, &self.theme)
+            .with_context(|| format!("Highlighting a {language} code block"))
+    }
+
+    /// Syntax highlight code for the terminal with the printer's theme, returning one line per line of code.
+    ///
+    /// `language` can be a language name or a file extension.
+    pub fn highlight(&self, language: &str, content: &str) -> Vec<String> {
+        let syntax = self
+            .syntax_set
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
+        let mut escaped_lines = vec![];
+        for line in syntect::util::LinesWithEndings::from(content) {
+            let regions = h.highlight_line(line, &self.syntax_set).unwrap();
+            let mut escaped = self
+                .color_mode
+                .escape_highlighted(&regions[..])
+                .trim_end_matches('\n')
+                .to_string();
+            if self.color_mode != ColorMode::Plain {
+                // Force a style reset
+                escaped.push_str("\x1b[0m");
+            }
+            escaped_lines.push(escaped);
         }
+        escaped_lines
     }
 
-    pub fn print(&self, text_output: &TextOutput) {
+    pub fn print(&mut self, text_output: &TextOutput) {
         match text_output {
             TextOutput::Text(text) => {
-                print!("{text}");
+                self.write_wrapped(text, str::to_string);
             }
             TextOutput::Bold(text) => {
-                print!("{}", console::style(text).bold());
+                self.write_wrapped(text, |word| console::style(word).bold().to_string());
             }
             TextOutput::Italic(text) => {
-                print!("{}", console::style(text).italic());
+                self.write_wrapped(text, |word| console::style(word).italic().to_string());
+            }
+            TextOutput::Strikethrough(text) => {
+                self.write_wrapped(text, |word| {
+                    console::style(word).strikethrough().to_string()
+                });
             }
             TextOutput::InlineCode(text) => {
-                print!("{}", console::style(text).dim());
+                self.write_wrapped(text, |word| console::style(word).dim().to_string());
             }
             TextOutput::CodeBlock { language, content } => {
-                let syntax = self
-                    .syntax_set
-                    .find_syntax_by_token(language)
-                    .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-                let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
-                for line in syntect::util::LinesWithEndings::from(content) {
-                    let regions = h.highlight_line(line, &self.syntax_set).unwrap();
-                    print!(
-                        "{}",
-                        syntect::util::as_24_bit_terminal_escaped(&regions[..], true)
-                    );
+                self.code_block_count += 1;
+                self.start_line();
+                self.write(
+                    console::style(format!("[{}] {language}", self.code_block_count))
+                        .dim()
+                        .to_string(),
+                );
+                self.new_line();
+
+                // Code blocks are never wrapped
+                for line in self.highlight(language, content) {
+                    self.start_line();
+                    self.write(line);
+                    self.new_line();
                 }
-                // Force a style reset
-                println!("\x1b[0m\n");
+                self.new_line();
+            }
+            TextOutput::Link { text, url } => {
+                if text == url || text.is_empty() {
+                    self.write_wrapped(url, |word| console::style(word).underlined().to_string());
+                } else {
+                    self.write_wrapped(text, |word| console::style(word).underlined().to_string());
+                    self.write_wrapped(&format!(" ({url})"), |word| {
+                        console::style(word).dim().to_string()
+                    });
+                }
+            }
+            TextOutput::Table { alignments, rows } => {
+                // Tables are never wrapped
+                for line in render_table(alignments, rows) {
+                    self.start_line();
+                    self.write(line);
+                    self.new_line();
+                }
+            }
+            TextOutput::TaskMarker(done) => {
+                // The checkbox is part of the item's marker so continuation lines are indented past it
+                if let Some(last) = self.list_indents.last_mut() {
+                    *last += 2;
+                }
+                self.start_line();
+                if *done {
+                    self.write(format!("{} ", console::style("☑").green()));
+                } else {
+                    self.write("☐ ");
+                }
+                self.column += 2;
+            }
+            TextOutput::ListItemStart(marker) => {
+                self.list_indents.push(console::measure_text_width(marker));
+                self.pending_list_marker = Some(marker.clone());
+            }
+            TextOutput::ListItemEnd => {
+                self.list_indents.pop();
+                self.pending_list_marker = None;
+            }
+            TextOutput::Html(html) => {
+                self.write_wrapped(html, |word| console::style(word).dim().to_string());
+            }
+            TextOutput::HorizontalRule => {
+                self.start_line();
+                let width = match self.width() {
+                    // Not a terminal
+                    usize::MAX => 80,
+                    width => width.saturating_sub(self.column),
+                };
+                self.write(console::style("─".repeat(width)).dim());
+                self.column += width;
+            }
+            TextOutput::BlockQuoteStart => {
+                self.quote_depth += 1;
+            }
+            TextOutput::BlockQuoteEnd => {
+                self.quote_depth = self.quote_depth.saturating_sub(1);
             }
             TextOutput::Newline => {
-                println!();
+                self.new_line();
             }
         }
+        let _ = self.writer.flush();
     }
 }
 
+/// Get the table cell text is currently being added to.
+fn current_table_cell(
+    table: &mut Option<(Vec<pulldown_cmark::Alignment>, Vec<Vec<String>>)>,
+) -> Option<&mut String> {
+    table
+        .as_mut()
+        .and_then(|(_, rows)| rows.last_mut())
+        .and_then(|row| row.last_mut())
+}
+
 pub fn parse_text(text: &str, output: &mut Vec<TextOutput>) {
     let mut options = pulldown_cmark::Options::empty();
     options.insert(pulldown_cmark::Options::ENABLE_GFM);
+    options.insert(pulldown_cmark::Options::ENABLE_TABLES);
+    options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
+    options.insert(pulldown_cmark::Options::ENABLE_TASKLISTS);
     let parser = pulldown_cmark::Parser::new_ext(text, options);
 
     let mut code_block_language = None;
     let mut accumulated_text = String::with_capacity(16);
     let mut emphasised = false;
     let mut strong = false;
-    let mut list_indent = None;
+    let mut strikethrough = false;
     let mut list_index = vec![];
+    // The URL and text of the link being parsed
+    let mut link: Option<(String, String)> = None;
+    // The alignments and rows of the table being parsed
+    let mut table = None;
+    let mut html_block: Option<String> = None;
 
     let mut current_heading_level = 0;
     let mut thoughts_heading = None;
@@ -107,45 +425,112 @@ pub fn parse_text(text: &str, output: &mut Vec<TextOutput>) {
             pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Strong) => {
                 strong = false;
             }
-            pulldown_cmark::Event::Start(pulldown_cmark::Tag::List(index)) => {
-                list_index.push(index);
-                list_indent = Some(match list_indent {
-                    None => 1i64,
-                    Some(x) => {
-                        output.push(TextOutput::Newline);
-                        x + 2
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Strikethrough) => {
+                strikethrough = true;
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Strikethrough) => {
+                strikethrough = false;
+            }
+            pulldown_cmark::Event::Start(
+                pulldown_cmark::Tag::Link { dest_url, .. }
+                | pulldown_cmark::Tag::Image { dest_url, .. },
+            ) => {
+                link = Some((dest_url.to_string(), String::new()));
+            }
+            pulldown_cmark::Event::End(
+                pulldown_cmark::TagEnd::Link | pulldown_cmark::TagEnd::Image,
+            ) => {
+                if let Some((url, text)) = link.take() {
+                    if let Some(cell) = current_table_cell(&mut table) {
+                        if text == url {
+                            cell.push_str(&url);
+                        } else {
+                            cell.push_str(&format!("{text} ({url})"));
+                        }
+                    } else {
+                        output.push(TextOutput::Link { text, url });
                     }
-                });
+                }
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::BlockQuote(kind)) => {
+                output.push(TextOutput::BlockQuoteStart);
+                if let Some(kind) = kind {
+                    let label = match kind {
+                        pulldown_cmark::BlockQuoteKind::Note => "Note",
+                        pulldown_cmark::BlockQuoteKind::Tip => "Tip",
+                        pulldown_cmark::BlockQuoteKind::Important => "Important",
+                        pulldown_cmark::BlockQuoteKind::Warning => "Warning",
+                        pulldown_cmark::BlockQuoteKind::Caution => "Caution",
+                    };
+                    output.push(TextOutput::Bold(label.to_string()));
+                    output.push(TextOutput::Newline);
+                }
             }
-            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::List(_)) => match list_indent {
-                None => tracing::error!("Markdown list ended without start"),
-                Some(1) => {
-                    list_indent = None;
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::BlockQuote(_)) => {
+                output.push(TextOutput::BlockQuoteEnd);
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Table(alignments)) => {
+                table = Some((alignments, vec![]));
+            }
+            pulldown_cmark::Event::Start(
+                pulldown_cmark::Tag::TableHead | pulldown_cmark::Tag::TableRow,
+            ) => {
+                if let Some((_, rows)) = &mut table {
+                    rows.push(vec![]);
+                }
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::TableCell) => {
+                if let Some(row) = table.as_mut().and_then(|(_, rows)| rows.last_mut()) {
+                    row.push(String::new());
+                }
+            }
+            pulldown_cmark::Event::End(
+                pulldown_cmark::TagEnd::TableHead
+                | pulldown_cmark::TagEnd::TableRow
+                | pulldown_cmark::TagEnd::TableCell,
+            ) => {}
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Table) => {
+                if let Some((alignments, rows)) = table.take() {
+                    output.push(TextOutput::Table { alignments, rows });
                     output.push(TextOutput::Newline);
                 }
-                Some(x) => {
-                    list_indent = Some(x - 2);
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::List(index)) => {
+                if !list_index.is_empty() {
+                    output.push(TextOutput::Newline);
                 }
-            },
-            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Item) => {
-                let indent = list_indent.unwrap_or(0);
-                let mut prefix = String::with_capacity(indent as usize + 1);
-                for _ in 0..indent {
-                    prefix.push(' ');
+                list_index.push(index);
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::List(_)) => {
+                if list_index.pop().is_none() {
+                    tracing::error!("Markdown list ended without start");
+                } else if list_index.is_empty() {
+                    output.push(TextOutput::Newline);
                 }
-                match list_index.last_mut() {
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Item) => {
+                let marker = match list_index.last_mut() {
                     Some(Some(num)) => {
-                        prefix.push_str(&format!("{}. ", *num));
+                        let marker = format!("{}. ", *num);
                         *num += 1;
+                        marker
                     }
-                    _ => {
-                        prefix.push_str("• ");
-                    }
-                }
-                output.push(TextOutput::Text(prefix));
+                    _ => "• ".to_string(),
+                };
+                output.push(TextOutput::ListItemStart(marker));
             }
             pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Item) => {
                 output.push(TextOutput::Newline);
+                output.push(TextOutput::ListItemEnd);
+            }
+            pulldown_cmark::Event::TaskListMarker(done) => {
+                // The checkbox replaces the bullet
+                if let Some(TextOutput::ListItemStart(marker)) = output.last_mut() {
+                    if marker == "• " {
+                        marker.clear();
+                    }
+                }
+                output.push(TextOutput::TaskMarker(done));
             }
             pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(kind)) => {
                 code_block_language = Some(match kind {
@@ -162,16 +547,28 @@ pub fn parse_text(text: &str, output: &mut Vec<TextOutput>) {
 
                 if code_block_language.is_some() {
                     accumulated_text.push_str(&text_content);
+                } else if let Some((_, link_text)) = &mut link {
+                    link_text.push_str(&text_content);
+                } else if let Some(cell) = current_table_cell(&mut table) {
+                    cell.push_str(&text_content);
                 } else if strong || current_heading_level == 1 {
                     output.push(TextOutput::Bold(text_content.to_string()));
                 } else if emphasised {
                     output.push(TextOutput::Italic(text_content.to_string()));
+                } else if strikethrough {
+                    output.push(TextOutput::Strikethrough(text_content.to_string()));
                 } else {
                     output.push(TextOutput::Text(text_content.to_string()));
                 }
             }
             pulldown_cmark::Event::Code(code) => {
-                output.push(TextOutput::InlineCode(code.to_string()));
+                if let Some((_, link_text)) = &mut link {
+                    link_text.push_str(&code);
+                } else if let Some(cell) = current_table_cell(&mut table) {
+                    cell.push_str(&code);
+                } else {
+                    output.push(TextOutput::InlineCode(code.to_string()));
+                }
             }
             pulldown_cmark::Event::End(pulldown_cmark::TagEnd::CodeBlock) => {
                 output.push(TextOutput::CodeBlock {
@@ -180,9 +577,288 @@ pub fn parse_text(text: &str, output: &mut Vec<TextOutput>) {
                 });
                 accumulated_text = String::with_capacity(16);
             }
+            pulldown_cmark::Event::SoftBreak => {
+                if let Some((_, link_text)) = &mut link {
+                    link_text.push(' ');
+                } else {
+                    output.push(TextOutput::Text(" ".to_string()));
+                }
+            }
+            pulldown_cmark::Event::HardBreak => {
+                output.push(TextOutput::Newline);
+            }
+            pulldown_cmark::Event::Rule => {
+                output.push(TextOutput::HorizontalRule);
+                output.push(TextOutput::Newline);
+                output.push(TextOutput::Newline);
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::HtmlBlock) => {
+                html_block = Some(String::new());
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::HtmlBlock) => {
+                if let Some(html) = html_block.take() {
+                    for line in html.trim_end().lines() {
+                        output.push(TextOutput::Html(line.to_string()));
+                        output.push(TextOutput::Newline);
+                    }
+                    output.push(TextOutput::Newline);
+                }
+            }
+            pulldown_cmark::Event::Html(html) => {
+                if let Some(html_block) = &mut html_block {
+                    html_block.push_str(&html);
+                } else {
+                    output.push(TextOutput::Html(html.to_string()));
+                }
+            }
+            pulldown_cmark::Event::InlineHtml(html) => {
+                if let Some(cell) = current_table_cell(&mut table) {
+                    cell.push_str(&html);
+                } else {
+                    output.push(TextOutput::Html(html.to_string()));
+                }
+            }
             x => {
                 tracing::warn!("Unhandled Markdown event: {:?}", x);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<TextOutput> {
+        let mut output = vec![];
+        parse_text(text, &mut output);
+        output
+    }
+
+    fn text(text: &str) -> TextOutput {
+        TextOutput::Text(text.to_string())
+    }
+
+    #[test]
+    fn table() {
+        let output = parse("| Name | Size |\n|:-----|-----:|\n| a | 10 |\n| bbb | 2 |\n");
+        assert_eq!(
+            output,
+            vec![
+                TextOutput::Table {
+                    alignments: vec![
+                        pulldown_cmark::Alignment::Left,
+                        pulldown_cmark::Alignment::Right
+                    ],
+                    rows: vec![
+                        vec!["Name".to_string(), "Size".to_string()],
+                        vec!["a".to_string(), "10".to_string()],
+                        vec!["bbb".to_string(), "2".to_string()],
+                    ],
+                },
+                TextOutput::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn table_rendering() {
+        console::set_colors_enabled(false);
+        let lines = render_table(
+            &[
+                pulldown_cmark::Alignment::Left,
+                pulldown_cmark::Alignment::Center,
+                pulldown_cmark::Alignment::Right,
+            ],
+            &[
+                vec!["Name".to_string(), "Kind".to_string(), "Size".to_string()],
+                vec!["a".to_string(), "dir".to_string(), "10".to_string()],
+            ],
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "┌──────┬──────┬──────┐",
+                "│ Name │ Kind │ Size │",
+                "├──────┼──────┼──────┤",
+                "│ a    │ dir  │   10 │",
+                "└──────┴──────┴──────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn block_quote() {
+        let output = parse("> quoted\n\nafter");
+        assert_eq!(
+            output,
+            vec![
+                TextOutput::BlockQuoteStart,
+                text("quoted"),
+                TextOutput::Newline,
+                TextOutput::Newline,
+                TextOutput::BlockQuoteEnd,
+                text("after"),
+                TextOutput::Newline,
+                TextOutput::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn block_quote_alert() {
+        let output = parse("> [!WARNING]\n> careful");
+        assert_eq!(
+            output[..3],
+            [
+                TextOutput::BlockQuoteStart,
+                TextOutput::Bold("Warning".to_string()),
+                TextOutput::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn link() {
+        let output = parse("see [the docs](https://example.com/docs) and <https://bucket.bot>");
+        assert_eq!(
+            output[..4],
+            [
+                text("see "),
+                TextOutput::Link {
+                    text: "the docs".to_string(),
+                    url: "https://example.com/docs".to_string(),
+                },
+                text(" and "),
+                TextOutput::Link {
+                    text: "https://bucket.bot".to_string(),
+                    url: "https://bucket.bot".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn strikethrough() {
+        let output = parse("~~old~~ new");
+        assert_eq!(
+            output[..2],
+            [TextOutput::Strikethrough("old".to_string()), text(" new")]
+        );
+    }
+
+    #[test]
+    fn task_list() {
+        let output = parse("- [x] done\n- [ ] todo\n");
+        assert_eq!(
+            output,
+            vec![
+                TextOutput::ListItemStart(String::new()),
+                TextOutput::TaskMarker(true),
+                text("done"),
+                TextOutput::Newline,
+                TextOutput::ListItemEnd,
+                TextOutput::ListItemStart(String::new()),
+                TextOutput::TaskMarker(false),
+                text("todo"),
+                TextOutput::Newline,
+                TextOutput::ListItemEnd,
+                TextOutput::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn horizontal_rule() {
+        let output = parse("above\n\n---\n\nbelow");
+        assert_eq!(
+            output[3..6],
+            [
+                TextOutput::HorizontalRule,
+                TextOutput::Newline,
+                TextOutput::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks() {
+        let output = parse("soft\nbreak  \nhard");
+        assert_eq!(
+            output[..5],
+            [
+                text("soft"),
+                text(" "),
+                text("break"),
+                TextOutput::Newline,
+                text("hard"),
+            ]
+        );
+    }
+
+    #[test]
+    fn html() {
+        let output = parse("<div>\nblock\n</div>\n\ninline <br> html");
+        assert_eq!(
+            output,
+            vec![
+                TextOutput::Html("<div>".to_string()),
+                TextOutput::Newline,
+                TextOutput::Html("block".to_string()),
+                TextOutput::Newline,
+                TextOutput::Html("</div>".to_string()),
+                TextOutput::Newline,
+                TextOutput::Newline,
+                text("inline "),
+                TextOutput::Html("<br>".to_string()),
+                text(" html"),
+                TextOutput::Newline,
+                TextOutput::Newline,
+            ]
+        );
+    }
+
+    fn render(text: &str, width: usize) -> String {
+        console::set_colors_enabled(false);
+        let mut printer = Printer::with_writer(
+            Vec::new(),
+            crate::color_mode::load_theme(None).unwrap(),
+            ColorMode::Plain,
+            Some(width),
+        );
+        for output in parse(text) {
+            printer.print(&output);
+        }
+        String::from_utf8(printer.writer).unwrap()
+    }
+
+    #[test]
+    fn wrap_paragraph() {
+        assert_eq!(
+            render("the quick brown fox jumps over the lazy dog", 20),
+            "the quick brown fox \njumps over the lazy \ndog\n\n"
+        );
+    }
+
+    #[test]
+    fn wrap_list_item() {
+        assert_eq!(
+            render("- the quick brown fox jumps over\n- dog", 20),
+            " • the quick brown \n   fox jumps over\n • dog\n\n"
+        );
+    }
+
+    #[test]
+    fn wrap_block_quote() {
+        assert_eq!(
+            render("> the quick brown fox jumps", 20),
+            "│ the quick brown \n│ fox jumps\n│ \n"
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_not_wrapped() {
+        let rendered = render("```\nthe quick brown fox jumps over the lazy dog\n```", 20);
+        assert!(rendered.contains("the quick brown fox jumps over the lazy dog"));
+    }
+}
@@ -0,0 +1,79 @@
+//! A spinner showing what `ask` is waiting on.
+
+use std::{cell::Cell, sync::Mutex};
+
+/// The maximum number of characters of a tool's input to show in the status line.
+const MAX_INPUT_SUMMARY_CHARS: usize = 60;
+
+/// The spinner currently being shown.
+static SPINNER: Mutex<Option<indicatif::ProgressBar>> = Mutex::new(None);
+
+thread_local! {
+    /// The number of calls to `suspend` the current thread is inside.
+    static SUSPEND_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Show a spinner with a message and the elapsed time, replacing any existing spinner.
+pub fn start(message: impl Into<String>) {
+    let spinner = indicatif::ProgressBar::new_spinner()
+        .with_style(indicatif::ProgressStyle::with_template("{spinner} {msg} ({elapsed})").unwrap())
+        .with_message(message.into());
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+    if let Some(previous) = SPINNER.lock().unwrap().replace(spinner) {
+        previous.finish_and_clear();
+    }
+}
+
+/// Show a spinner for a running tool with a short summary of its input.
+pub fn start_tool(name: &str, input: &serde_json::Value) {
+    start(format!("running {name} {}", summarize_input(input)));
+}
+
+/// Remove the spinner so other output can be printed.
+pub fn clear() {
+    if let Some(spinner) = SPINNER.lock().unwrap().take() {
+        spinner.finish_and_clear();
+    }
+}
+
+/// Hide the spinner while `f` runs, for example to prompt the user, and then show it again.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    let spinner = SPINNER.lock().unwrap().clone();
+    SUSPEND_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let result = match spinner {
+        Some(spinner) => spinner.suspend(f),
+        None => f(),
+    };
+    SUSPEND_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    result
+}
+
+/// Check whether the spinner is hidden by `suspend` on the current thread.
+#[cfg(test)]
+pub fn is_suspended() -> bool {
+    SUSPEND_DEPTH.with(|depth| depth.get() > 0)
+}
+
+/// Summarize a tool's input on one line, for example `paths=["a.rs","b.rs"]`.
+fn summarize_input(input: &serde_json::Value) -> String {
+    let summary = match input {
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => format!("{key}={value}"),
+                value => format!("{key}={value}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        input => input.to_string(),
+    };
+    let summary = summary.replace('\n', " ");
+
+    if summary.chars().count() > MAX_INPUT_SUMMARY_CHARS {
+        let mut truncated: String = summary.chars().take(MAX_INPUT_SUMMARY_CHARS).collect();
+        truncated.push('…');
+        truncated
+    } else {
+        summary
+    }
+}
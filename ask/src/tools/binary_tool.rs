@@ -1,7 +1,6 @@
 //! Tools that allow direct access to binaries to scripts.
 
-use anyhow::Context;
-
+use super::command_runner::{CommandRunner, CommandSpec, OutputMode, SystemCommandRunner};
 use super::RustTool;
 use crate::tools::ToolPrerequisites;
 
@@ -20,46 +19,109 @@ pub struct BinaryTool {
     description: String,
     send_output: bool,
     print_to_console: bool,
+    runner: std::sync::Arc<dyn CommandRunner>,
 }
 
 static USER_CAN_SEE_OUTPUT: &str = "The user will see the output of the command.";
 static USER_CANT_SEE_OUTPUT: &str = "The user will not see the output of the command so if relevant you may want to give them snippets or a summary of the output if an error occurs.";
 
+/// Describe a tool which runs a binary, including what the LLM and the user will see of its output.
+pub(super) fn describe(
+    binary: &str,
+    description: &str,
+    send_output: bool,
+    print_to_console: bool,
+) -> String {
+    let mut base_description = vec![
+        description.to_string(),
+        if print_to_console {
+            USER_CAN_SEE_OUTPUT
+        } else {
+            USER_CANT_SEE_OUTPUT
+        }
+        .to_string(),
+    ];
+    if send_output {
+        base_description.push(format!(
+            "You will receive the return code, stdout, and stderr from `{binary}`."
+        ));
+        base_description.push(format!("The stdout and stderr from {binary} will be returned sent back enclosed in XML tags - the `<stdout>` tag will have stdout and the `<stderr>` tag will have stderr."));
+    } else {
+        base_description.push(format!(
+            "You will receive the return code `{binary}`, but not the stdout or stderr."
+        ));
+    }
+    base_description.join("\n")
+}
+
+/// Run a binary and describe the outcome for the LLM.
+///
+/// Only the return code is described unless `send_output` is set, and the output is shown to the user if `print_to_console` is set.
+pub(super) fn execute(
+    runner: &dyn CommandRunner,
+    binary: &str,
+    arguments: &[String],
+    working_directory: Option<&str>,
+    send_output: bool,
+    print_to_console: bool,
+) -> anyhow::Result<String> {
+    tracing::info!("Running: {} with the arguments: {:?}", binary, arguments);
+
+    let mut command = CommandSpec::new(binary)
+        .args(arguments)
+        .output_mode(if send_output {
+            OutputMode::Capture
+        } else if print_to_console {
+            OutputMode::Inherit
+        } else {
+            OutputMode::Discard
+        });
+    // An empty working directory would fail so use the current directory instead
+    if let Some(working_directory) = working_directory.filter(|directory| !directory.is_empty()) {
+        command = command.working_directory(crate::path_utils::expand_path(working_directory)?);
+    }
+    let output = runner.run(&command)?;
+
+    let mut result = vec![format!(
+        "The return code was {code}",
+        code = output.code.unwrap_or(-1)
+    )];
+    if send_output {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        result.push(format!("<stdout>\n{}</stdout>", &stdout));
+        result.push(format!("<stderr>\n{}</stderr>", &stderr,));
+        if print_to_console {
+            crate::status::suspend(|| {
+                println!("{stdout}");
+                eprintln!("{stderr}");
+            });
+        }
+    }
+
+    Ok(result.join("\n"))
+}
+
 impl BinaryTool {
     /// Create a binary tool which sends stdout and stderr to the LLM.
     pub fn new_with_output(binary: &str, description: &str, print_to_console: bool) -> Self {
-        let base_description = [
-            description.to_string(),
-            if print_to_console { USER_CAN_SEE_OUTPUT } else { USER_CANT_SEE_OUTPUT }.to_string(),
-            format!("You will receive the return code, stdout, and stderr from `{binary}`."),
-            format!("The stdout and stderr from {binary} will be returned sent back enclosed in XML tags - the `<stdout>` tag will have stdout and the `<stderr>` tag will have stderr."),
-        ];
-
         Self {
             binary: binary.to_string(),
-            description: base_description.join("\n"),
+            description: describe(binary, description, true, print_to_console),
             send_output: true,
             print_to_console,
+            runner: std::sync::Arc::new(SystemCommandRunner),
         }
     }
 
     /// Create a binary tool which only sends the return code to the LLM.
     pub fn new_without_output(binary: &str, description: &str, print_to_console: bool) -> Self {
-        let base_description = [
-            description.to_string(),
-            if print_to_console {
-                USER_CAN_SEE_OUTPUT
-            } else {
-                USER_CANT_SEE_OUTPUT
-            }
-            .to_string(),
-            format!("You will receive the return code `{binary}`, but not the stdout or stderr."),
-        ];
         Self {
             binary: binary.to_string(),
-            description: base_description.join("\n"),
+            description: describe(binary, description, false, print_to_console),
             send_output: false,
             print_to_console,
+            runner: std::sync::Arc::new(SystemCommandRunner),
         }
     }
 }
@@ -78,55 +140,92 @@ impl RustTool for BinaryTool {
     fn get_prequisites(&self) -> ToolPrerequisites {
         ToolPrerequisites {
             binaries: vec![self.binary.clone()],
+            ..Default::default()
         }
     }
 
+    fn get_truncation_hint(&self) -> String {
+        format!("Run `{}` again with arguments that produce less output, for example by selecting specific files or reducing verbosity, to see the omitted part", self.binary)
+    }
+
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        &[
+            super::policy::RuleField::AllowedPaths,
+            super::policy::RuleField::DeniedPaths,
+            super::policy::RuleField::AllowedSubcommands,
+        ]
+    }
+
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        super::policy::check_subcommand(&input.arguments, rule)?;
+        if let Some(working_directory) = &input.working_directory {
+            super::policy::check_paths([working_directory.as_str()], rule)?;
+        }
+        Ok(())
+    }
+
     async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
-        tracing::info!(
-            "Running: {} with the arguments: {:?}",
+        execute(
+            self.runner.as_ref(),
             &self.binary,
-            &input.arguments
+            &input.arguments,
+            input.working_directory.as_deref(),
+            self.send_output,
+            self.print_to_console,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::FakeCommandRunner;
+
+    const TAR: &[&str] = &["tar", "-xf", "archive.tar"];
+
+    /// The arguments of the `tar` command line.
+    fn arguments() -> Vec<String> {
+        TAR[1..].iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn sends_output() {
+        let runner = FakeCommandRunner::default().with_output(TAR, 2, "out\n", "err\n");
+        let result = execute(&runner, "tar", &arguments(), Some("dir"), true, false).unwrap();
+        assert_eq!(
+            result,
+            "The return code was 2\n<stdout>\nout\n</stdout>\n<stderr>\nerr\n</stderr>"
         );
 
-        let mut base_command = std::process::Command::new(&self.binary);
-        if self.send_output {
-            base_command
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped());
-        } else if !self.print_to_console {
-            base_command
-                .stdout(std::process::Stdio::null())
-                .stderr(std::process::Stdio::null());
-        } else {
-            base_command
-                .stdout(std::process::Stdio::inherit())
-                .stderr(std::process::Stdio::inherit());
-        };
-
-        let working_directory = crate::path_utils::expand_path(
-            input.working_directory.clone().unwrap_or_default().as_str(),
-        )?;
-        let output = base_command
-            .args(input.arguments)
-            .current_dir(working_directory)
-            .output()
-            .context("Failed to execute process")?;
-
-        let mut result = vec![format!(
-            "The return code was {code}",
-            code = output.status.code().unwrap_or(-1)
-        )];
-        if self.send_output {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            result.push(format!("<stdout>\n{}</stdout>", &stdout));
-            result.push(format!("<stderr>\n{}</stderr>", &stderr,));
-            if self.print_to_console {
-                println!("{stdout}");
-                eprintln!("{stderr}");
-            }
-        }
+        let commands = runner.get_commands();
+        assert_eq!(commands[0].working_directory.as_deref(), Some("dir"));
+        assert_eq!(commands[0].output_mode, OutputMode::Capture);
+        assert_eq!(runner.get_suspended(), [false]);
+    }
+
+    #[test]
+    fn only_sends_the_return_code() {
+        let runner = FakeCommandRunner::default().with_output(TAR, 0, "", "");
+        let result = execute(&runner, "tar", &arguments(), Some(""), false, true).unwrap();
+        assert_eq!(result, "The return code was 0");
+
+        let commands = runner.get_commands();
+        assert_eq!(commands[0].working_directory, None);
+        assert_eq!(commands[0].output_mode, OutputMode::Inherit);
+        assert_eq!(runner.get_suspended(), [true]);
+
+        let runner = FakeCommandRunner::default().with_output(TAR, 0, "", "");
+        execute(&runner, "tar", &arguments(), None, false, false).unwrap();
+        assert_eq!(runner.get_commands()[0].output_mode, OutputMode::Discard);
+    }
 
-        Ok(result.join("\n"))
+    #[test]
+    fn binary_fails_to_start() {
+        let runner = FakeCommandRunner::default();
+        assert!(execute(&runner, "tar", &arguments(), None, true, false).is_err());
     }
 }
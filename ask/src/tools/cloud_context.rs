@@ -1,7 +1,7 @@
 use core::str;
+use std::sync::Arc;
 
-use anyhow::Context;
-
+use super::command_runner::{CommandRunner, CommandSpec, SystemCommandRunner};
 use super::RustTool;
 use crate::tools::ToolPrerequisites;
 
@@ -10,7 +10,17 @@ use crate::tools::ToolPrerequisites;
 pub struct CloudContextInput {}
 
 /// A tool that provides general information about available cloud resources.
-pub struct CloudContextTool;
+pub struct CloudContextTool {
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl CloudContextTool {
+    pub fn new() -> Self {
+        Self {
+            runner: Arc::new(SystemCommandRunner),
+        }
+    }
+}
 
 impl RustTool for CloudContextTool {
     type Input = CloudContextInput;
@@ -24,33 +34,29 @@ impl RustTool for CloudContextTool {
     }
 
     fn get_prequisites(&self) -> ToolPrerequisites {
-        ToolPrerequisites { binaries: vec![] }
+        ToolPrerequisites {
+            binaries: vec!["kubectl".to_string(), "aws".to_string()],
+            ..Default::default()
+        }
     }
 
-    async fn run(self: std::sync::Arc<Self>, _input: Self::Input) -> anyhow::Result<String> {
-        let kubectl_output = std::process::Command::new("kubectl")
-            .arg("config")
-            .arg("view")
-            .arg("--output=jsonpath={.contexts}")
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .output()
-            .context("Failed to execute kubectl config view")?;
-        let aws_output = std::process::Command::new("aws")
-            .arg("configure")
-            .arg("list-profiles")
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .output()
-            .context("Failed to execute aws configure list-profiles")?;
+    async fn run(self: Arc<Self>, _input: Self::Input) -> anyhow::Result<String> {
+        let kubectl_output = self.runner.run(&CommandSpec::new("kubectl").args([
+            "config",
+            "view",
+            "--output=jsonpath={.contexts}",
+        ]))?;
+        let aws_output = self
+            .runner
+            .run(&CommandSpec::new("aws").args(["configure", "list-profiles"]))?;
 
         let mut output = serde_json::json!({});
-        output["kubernetes"] = if kubectl_output.status.success() {
+        output["kubernetes"] = if kubectl_output.success() {
             serde_json::from_slice(&kubectl_output.stdout)?
         } else {
             serde_json::Value::Null
         };
-        output["aws_profiles"] = if aws_output.status.success() {
+        output["aws_profiles"] = if aws_output.success() {
             str::from_utf8(&aws_output.stdout)?
                 .lines()
                 .map(|line| serde_json::Value::String(line.to_string()))
@@ -63,3 +69,61 @@ impl RustTool for CloudContextTool {
         Ok(serde_json::to_string(&output)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::{block_on, FakeCommandRunner};
+
+    const KUBECTL: &[&str] = &["kubectl", "config", "view", "--output=jsonpath={.contexts}"];
+    const AWS: &[&str] = &["aws", "configure", "list-profiles"];
+
+    /// Run the tool and parse its output.
+    fn get_context(runner: FakeCommandRunner) -> serde_json::Value {
+        let tool = Arc::new(CloudContextTool {
+            runner: Arc::new(runner),
+        });
+        let output = block_on(RustTool::run(tool, CloudContextInput {})).unwrap();
+        serde_json::from_str(&output).unwrap()
+    }
+
+    #[test]
+    fn combines_kubernetes_and_aws() {
+        let context = get_context(
+            FakeCommandRunner::default()
+                .with_output(
+                    KUBECTL,
+                    0,
+                    r#"[{"name":"prod","context":{"cluster":"prod-cluster"}}]"#,
+                    "",
+                )
+                .with_output(AWS, 0, "default\nstaging\n", ""),
+        );
+        assert_eq!(
+            context,
+            serde_json::json!({
+                "kubernetes": [{ "name": "prod", "context": { "cluster": "prod-cluster" } }],
+                "aws_profiles": ["default", "staging"],
+            })
+        );
+    }
+
+    #[test]
+    fn failed_commands_are_null() {
+        let context = get_context(
+            FakeCommandRunner::default()
+                .with_output(KUBECTL, 1, "", "error: no configuration")
+                .with_output(AWS, 0, "default\n", ""),
+        );
+        assert_eq!(context["kubernetes"], serde_json::Value::Null);
+        assert_eq!(context["aws_profiles"], serde_json::json!(["default"]));
+
+        let context = get_context(
+            FakeCommandRunner::default()
+                .with_output(KUBECTL, 0, "[]", "")
+                .with_output(AWS, 255, "", "error"),
+        );
+        assert_eq!(context["kubernetes"], serde_json::json!([]));
+        assert_eq!(context["aws_profiles"], serde_json::Value::Null);
+    }
+}
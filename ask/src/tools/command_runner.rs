@@ -0,0 +1,342 @@
+//! Running external commands, behind a trait so the tools which run commands can be tested without running them.
+
+use std::io::{Read, Write};
+
+use anyhow::Context;
+
+/// What happens to the output of a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Capture stdout and stderr.
+    Capture,
+    /// Let the command write to the terminal.
+    Inherit,
+    /// Throw the output away.
+    Discard,
+}
+
+/// A command to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub arguments: Vec<String>,
+    /// The directory to run the command in, the current directory is used if it's `None`.
+    pub working_directory: Option<String>,
+    pub output_mode: OutputMode,
+    /// The bytes to write to the command's stdin, stdin is empty if it's `None` unless the output mode is `Inherit`.
+    pub stdin: Option<Vec<u8>>,
+    /// How long the command may run before it's killed, it can run forever if it's `None`.
+    pub timeout: Option<std::time::Duration>,
+}
+
+impl CommandSpec {
+    /// Create a command which runs a program without arguments and captures its output.
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            arguments: vec![],
+            working_directory: None,
+            output_mode: OutputMode::Capture,
+            stdin: None,
+            timeout: None,
+        }
+    }
+
+    /// Add an argument.
+    pub fn arg(mut self, argument: impl Into<String>) -> Self {
+        self.arguments.push(argument.into());
+        self
+    }
+
+    /// Add several arguments.
+    pub fn args<I: IntoIterator<Item = S>, S: Into<String>>(mut self, arguments: I) -> Self {
+        self.arguments.extend(arguments.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the directory to run the command in.
+    pub fn working_directory(mut self, working_directory: impl Into<String>) -> Self {
+        self.working_directory = Some(working_directory.into());
+        self
+    }
+
+    /// Set what happens to the command's output.
+    pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+
+    /// Set the bytes to write to the command's stdin.
+    pub fn stdin(mut self, stdin: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    /// Kill the command if it runs for longer than `timeout`.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// The outcome of a command which ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// The exit code, `None` if the command was killed by a signal.
+    pub code: Option<i32>,
+    /// The captured stdout, empty unless the output mode is `Capture`.
+    pub stdout: Vec<u8>,
+    /// The captured stderr, empty unless the output mode is `Capture`.
+    pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+    /// Check whether the command exited successfully.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Runs commands for tools.
+pub trait CommandRunner: Send + Sync {
+    /// Run a command to completion.
+    ///
+    /// Failing to start the command is an error, a command which exits unsuccessfully isn't.
+    /// The spinner is hidden while commands which use the terminal run so it doesn't draw over their prompts.
+    fn run(&self, command: &CommandSpec) -> anyhow::Result<CommandOutput> {
+        if command.output_mode == OutputMode::Inherit {
+            crate::status::suspend(|| self.execute(command))
+        } else {
+            self.execute(command)
+        }
+    }
+
+    /// Run a command to completion without changing the spinner, use `run` instead.
+    fn execute(&self, command: &CommandSpec) -> anyhow::Result<CommandOutput>;
+}
+
+/// Runs commands as processes.
+pub struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn execute(&self, command: &CommandSpec) -> anyhow::Result<CommandOutput> {
+        let mut process = std::process::Command::new(&command.program);
+        process.args(&command.arguments);
+        if let Some(working_directory) = &command.working_directory {
+            process.current_dir(working_directory);
+        }
+        let (stdout, stderr) = match command.output_mode {
+            OutputMode::Capture => (std::process::Stdio::piped(), std::process::Stdio::piped()),
+            OutputMode::Inherit => (
+                std::process::Stdio::inherit(),
+                std::process::Stdio::inherit(),
+            ),
+            OutputMode::Discard => (std::process::Stdio::null(), std::process::Stdio::null()),
+        };
+        let mut child = process
+            .stdin(match (&command.stdin, command.output_mode) {
+                (Some(_), _) => std::process::Stdio::piped(),
+                // Commands which use the terminal can prompt the user
+                (None, OutputMode::Inherit) => std::process::Stdio::inherit(),
+                (None, _) => std::process::Stdio::null(),
+            })
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .with_context(|| format!("Failed to execute {}", command.program))?;
+
+        // Write and read from threads so a command which writes a lot before reading its input can't deadlock
+        let writer = command
+            .stdin
+            .clone()
+            .zip(child.stdin.take())
+            .map(|(input, mut stdin)| std::thread::spawn(move || stdin.write_all(&input)));
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let status = match command.timeout {
+            Some(timeout) => wait_with_timeout(&mut child, timeout)
+                .with_context(|| format!("Failed to run {}", command.program))?,
+            None => child
+                .wait()
+                .with_context(|| format!("Failed to wait for {}", command.program))?,
+        };
+        if let Some(Ok(Err(e))) = writer.map(std::thread::JoinHandle::join) {
+            tracing::warn!("Failed to write the input of {}: {e}", command.program);
+        }
+
+        Ok(CommandOutput {
+            code: status.code(),
+            stdout: join_output(stdout).context("Failed to read the command's stdout")?,
+            stderr: join_output(stderr).context("Failed to read the command's stderr")?,
+        })
+    }
+}
+
+/// Read a command's output stream to the end on a thread.
+fn read_in_background<R: Read + Send + 'static>(
+    stream: Option<R>,
+) -> Option<std::thread::JoinHandle<std::io::Result<Vec<u8>>>> {
+    stream.map(|mut stream| {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            stream.read_to_end(&mut output).map(|_| output)
+        })
+    })
+}
+
+/// Get the output read by `read_in_background`, which is empty if the stream wasn't captured.
+fn join_output(
+    reader: Option<std::thread::JoinHandle<std::io::Result<Vec<u8>>>>,
+) -> anyhow::Result<Vec<u8>> {
+    let Some(reader) = reader else {
+        return Ok(vec![]);
+    };
+    Ok(reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("Reading the output panicked"))??)
+}
+
+/// Wait for a child process to exit, killing it if it runs for longer than `timeout`.
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: std::time::Duration,
+) -> anyhow::Result<std::process::ExitStatus> {
+    let started = std::time::Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if started.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("The command took more than {timeout:?}");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// A command runner which returns scripted outputs, for tests.
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Mutex;
+
+    use super::{CommandOutput, CommandRunner, CommandSpec};
+
+    /// Returns the output scripted for each command line and records the commands it was asked to run.
+    ///
+    /// Commands without a scripted output fail to start as if the program wasn't installed.
+    #[derive(Default)]
+    pub struct FakeCommandRunner {
+        outputs: Vec<(Vec<String>, CommandOutput)>,
+        /// Command lines which never exit.
+        hanging: Vec<Vec<String>>,
+        /// The commands which have been run and whether the spinner was hidden while they ran.
+        commands: Mutex<Vec<(CommandSpec, bool)>>,
+    }
+
+    impl FakeCommandRunner {
+        /// Script the output of a command line, the program followed by its arguments.
+        pub fn with_output(
+            mut self,
+            command_line: &[&str],
+            code: i32,
+            stdout: &str,
+            stderr: &str,
+        ) -> Self {
+            self.outputs.push((
+                command_line.iter().map(ToString::to_string).collect(),
+                CommandOutput {
+                    code: Some(code),
+                    stdout: stdout.as_bytes().to_vec(),
+                    stderr: stderr.as_bytes().to_vec(),
+                },
+            ));
+            self
+        }
+
+        /// Script a command line which never exits, so it fails once its timeout passes.
+        pub fn with_hang(mut self, command_line: &[&str]) -> Self {
+            self.hanging
+                .push(command_line.iter().map(ToString::to_string).collect());
+            self
+        }
+
+        /// Get the commands which have been run.
+        pub fn get_commands(&self) -> Vec<CommandSpec> {
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(command, _)| command.clone())
+                .collect()
+        }
+
+        /// Get whether the spinner was hidden while each command ran.
+        pub fn get_suspended(&self) -> Vec<bool> {
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(_, suspended)| *suspended)
+                .collect()
+        }
+    }
+
+    impl CommandRunner for FakeCommandRunner {
+        fn execute(&self, command: &CommandSpec) -> anyhow::Result<CommandOutput> {
+            self.commands
+                .lock()
+                .unwrap()
+                .push((command.clone(), crate::status::is_suspended()));
+            let command_line: Vec<&str> = std::iter::once(command.program.as_str())
+                .chain(command.arguments.iter().map(String::as_str))
+                .collect();
+            if self.hanging.iter().any(|hanging| *hanging == command_line) {
+                let timeout = command
+                    .timeout
+                    .expect("The command would never exit without a timeout");
+                anyhow::bail!("The command took more than {timeout:?}");
+            }
+            self.outputs
+                .iter()
+                .find(|(scripted, _)| *scripted == command_line)
+                .map(|(_, output)| output.clone())
+                .ok_or_else(|| anyhow::anyhow!("Failed to execute {}", command.program))
+        }
+    }
+
+    /// Run a future to completion, for testing async tools.
+    pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_stdin() {
+        let output = SystemCommandRunner
+            .run(&CommandSpec::new("cat").stdin("input"))
+            .unwrap();
+        assert!(output.success());
+        assert_eq!(output.stdout, b"input");
+    }
+
+    #[test]
+    fn kills_commands_which_time_out() {
+        let started = std::time::Instant::now();
+        let result = SystemCommandRunner.run(
+            &CommandSpec::new("sleep")
+                .arg("10")
+                .timeout(std::time::Duration::from_millis(100)),
+        );
+        assert!(format!("{:#}", result.unwrap_err()).contains("took more than 100ms"));
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
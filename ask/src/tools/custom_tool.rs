@@ -0,0 +1,213 @@
+//! Tools which run binaries, declared in the config.
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use productivity_config::{CustomToolConfig, CustomToolOutputMode};
+
+use super::command_runner::{CommandRunner, SystemCommandRunner};
+use super::{Tool, ToolDefinition, ToolPrerequisites};
+
+/// A tool declared in the user's config which runs a binary with templated arguments.
+pub struct CustomTool {
+    config: CustomToolConfig,
+    /// The description including what the LLM and the user will see of the output.
+    description: String,
+    /// Matches `{parameter}` placeholders in the argument template.
+    placeholder: regex::Regex,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl CustomTool {
+    /// Create a tool from its config.
+    pub fn new(config: CustomToolConfig) -> anyhow::Result<Self> {
+        let valid_name = regex::Regex::new("^[a-zA-Z0-9_-]{1,64}$")?;
+        if !valid_name.is_match(&config.name) {
+            anyhow::bail!(
+                "The custom tool name {:?} must only contain letters, digits, `_`, and `-`",
+                config.name
+            );
+        }
+
+        Ok(Self {
+            description: super::binary_tool::describe(
+                &config.binary,
+                &config.description,
+                config.output_mode == CustomToolOutputMode::Output,
+                config.print_to_console,
+            ),
+            placeholder: regex::Regex::new(r"\{([A-Za-z0-9_-]+)\}")?,
+            config,
+            runner: Arc::new(SystemCommandRunner),
+        })
+    }
+
+    /// Build the arguments to run the binary with from the tool's input.
+    fn render_arguments(&self, input: &serde_json::Value) -> Vec<String> {
+        let mut arguments = self.config.argument_prefix.clone();
+        for template in &self.config.argument_template {
+            let value_of = |name: &str| input.get(name).filter(|value| !value.is_null());
+
+            // A lone placeholder for an array becomes an argument per element
+            if let Some(captures) = self.placeholder.captures(template) {
+                if captures[0].len() == template.len() {
+                    if let Some(serde_json::Value::Array(values)) = value_of(&captures[1]) {
+                        arguments.extend(values.iter().map(argument_string));
+                        continue;
+                    }
+                }
+            }
+
+            let mut missing = false;
+            let argument = self
+                .placeholder
+                .replace_all(template, |captures: &regex::Captures| {
+                    value_of(&captures[1]).map_or_else(
+                        || {
+                            missing = true;
+                            String::new()
+                        },
+                        argument_string,
+                    )
+                })
+                .into_owned();
+            if !missing {
+                arguments.push(argument);
+            }
+        }
+        arguments
+    }
+}
+
+/// Convert a JSON value to an argument, strings are used as is and everything else as JSON.
+fn argument_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Create the tools declared in the config, skipping any which are invalid.
+pub fn get_custom_tools(configs: &[CustomToolConfig]) -> Vec<Arc<dyn Tool>> {
+    let mut tools: Vec<Arc<dyn Tool>> = Vec::with_capacity(configs.len());
+    for config in configs {
+        match CustomTool::new(config.clone()) {
+            Ok(tool) => tools.push(Arc::new(tool)),
+            Err(e) => tracing::warn!("Skipping the custom tool {}: {e}", config.name),
+        }
+    }
+    tools
+}
+
+impl Tool for CustomTool {
+    fn get_definition(&self) -> ToolDefinition {
+        let mut input_schema = match &self.config.parameters {
+            serde_json::Value::Object(schema) => schema.clone(),
+            _ => serde_json::Map::new(),
+        };
+        input_schema.insert("type".to_string(), serde_json::json!("object"));
+        input_schema
+            .entry("properties")
+            .or_insert_with(|| serde_json::json!({}));
+
+        ToolDefinition {
+            name: self.config.name.clone(),
+            description: self.description.clone(),
+            input_schema: serde_json::Value::Object(input_schema),
+        }
+    }
+
+    fn get_prequisites(&self) -> ToolPrerequisites {
+        ToolPrerequisites {
+            binaries: vec![self.config.binary.clone()],
+            ..Default::default()
+        }
+    }
+
+    fn get_truncation_hint(&self) -> String {
+        format!(
+            "Run the {} tool again with input that produces less output to see the omitted part",
+            self.config.name
+        )
+    }
+
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        &[super::policy::RuleField::AllowedSubcommands]
+    }
+
+    fn check_policy(
+        &self,
+        input: &serde_json::Value,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        super::policy::check_subcommand(&self.render_arguments(input), rule)
+    }
+
+    fn run(
+        self: Arc<Self>,
+        input: serde_json::Value,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>>>> {
+        Box::pin(async move {
+            super::binary_tool::execute(
+                self.runner.as_ref(),
+                &self.config.binary,
+                &self.render_arguments(&input),
+                None,
+                self.config.output_mode == CustomToolOutputMode::Output,
+                self.config.print_to_console,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::{block_on, FakeCommandRunner};
+
+    /// Create a `git log` tool which runs its command with a runner.
+    fn git_log(runner: Arc<FakeCommandRunner>) -> Arc<CustomTool> {
+        let config: CustomToolConfig = serde_json::from_value(serde_json::json!({
+            "name": "git_log",
+            "description": "Show the commit log.",
+            "binary": "git",
+            "argument_prefix": ["log", "--oneline"],
+            "argument_template": ["--max-count={count}", "{paths}"],
+        }))
+        .unwrap();
+        let mut tool = CustomTool::new(config).unwrap();
+        tool.runner = runner;
+        Arc::new(tool)
+    }
+
+    #[test]
+    fn runs_the_rendered_arguments() {
+        let runner = Arc::new(FakeCommandRunner::default().with_output(
+            &["git", "log", "--oneline", "--max-count=2", "src", "docs"],
+            0,
+            "abc123 Commit\n",
+            "",
+        ));
+        let result = block_on(
+            git_log(runner.clone())
+                .run(serde_json::json!({ "count": 2, "paths": ["src", "docs"] })),
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "The return code was 0\n<stdout>\nabc123 Commit\n</stdout>\n<stderr>\n</stderr>"
+        );
+        assert_eq!(runner.get_commands().len(), 1);
+    }
+
+    #[test]
+    fn leaves_out_missing_parameters() {
+        let runner = Arc::new(FakeCommandRunner::default().with_output(
+            &["git", "log", "--oneline"],
+            0,
+            "",
+            "",
+        ));
+        block_on(git_log(runner.clone()).run(serde_json::json!({}))).unwrap();
+        assert_eq!(runner.get_commands()[0].arguments, ["log", "--oneline"]);
+    }
+}
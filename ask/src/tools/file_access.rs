@@ -0,0 +1,233 @@
+//! The permission prompt shared by the tools which read files and the permissions the user remembered.
+
+use std::collections::HashSet;
+
+use anyhow::Context;
+
+/// How a tool accesses the paths it asks for, approvals for one kind of access don't approve the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    /// Reading a file or listing a directory.
+    Read,
+    /// Reading the content of every file under a directory.
+    Search,
+}
+
+/// A type to store access remembered permissions.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+pub struct AccessCache {
+    /// The set of paths which the user has previously allowed read access to.
+    allowed_read_paths: HashSet<String>,
+    /// The set of paths which the user has previously allowed searching.
+    #[serde(default)]
+    allowed_search_paths: HashSet<String>,
+}
+
+impl AccessCache {
+    /// Get the path of the cache file, which is in the `read_files` cache directory since it was the first tool to use it.
+    fn get_path() -> anyhow::Result<std::path::PathBuf> {
+        Ok(super::get_cache_dir("read_files")?.join("access.json"))
+    }
+
+    /// Load the cache, starting from an empty cache if it can't be read.
+    fn load(path: &std::path::Path) -> Self {
+        let Ok(file) = std::fs::File::open(path) else {
+            tracing::info!("No access cache found at {}", path.display());
+            return Self::default();
+        };
+        serde_json::from_reader(file).unwrap_or_else(|e| {
+            tracing::warn!("Failed to read access cache at {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// Write the cache back to disk.
+    fn save(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path).with_context(|| {
+            format!(
+                "Failed to create the access cache file at {}",
+                path.display()
+            )
+        })?;
+        serde_json::to_writer(file, self).with_context(|| {
+            format!(
+                "Failed to write the access cache file at {}",
+                path.display()
+            )
+        })
+    }
+
+    /// Get the paths allowed for a kind of access.
+    fn get_allowed_paths(&mut self, kind: AccessKind) -> &mut HashSet<String> {
+        match kind {
+            AccessKind::Read => &mut self.allowed_read_paths,
+            AccessKind::Search => &mut self.allowed_search_paths,
+        }
+    }
+}
+
+/// The outcome of asking the user for read access.
+pub struct ReadAccess {
+    /// The paths the user allowed reading.
+    pub allowed: Vec<String>,
+    /// The paths the user didn't allow reading.
+    pub denied: Vec<String>,
+}
+
+impl ReadAccess {
+    /// Describe the denied paths for the LLM, one line per path.
+    pub fn describe_denied(&self) -> String {
+        self.denied
+            .iter()
+            .map(|path| format!("The user chose not to allow you to read {path}.\n"))
+            .collect()
+    }
+}
+
+/// Ask the user to allow accessing paths unless they have allowed all of them before, remembering their choices.
+///
+/// Each request is a full path and the reason the LLM gave for accessing it.
+pub fn request_access(
+    kind: AccessKind,
+    requests: &[(String, String)],
+) -> anyhow::Result<ReadAccess> {
+    request_access_with_cache(&AccessCache::get_path()?, kind, requests, |choices| {
+        // Create a default selection of all paths (all allowed by default
+        let defaults: Vec<bool> = choices.iter().map(|_| true).collect();
+        let selection = crate::status::suspend(|| {
+            dialoguer::MultiSelect::new()
+                .with_prompt(match kind {
+                    AccessKind::Read => "Files to allow the LLM to read",
+                    AccessKind::Search => "Directories to allow the LLM to search",
+                })
+                .items(choices)
+                .defaults(&defaults)
+                .interact()
+        })
+        .context("From multi-select")?;
+        crate::audit::record_approval(selection.len() == choices.len());
+        Ok(selection)
+    })
+}
+
+/// Ask for access to paths with the access cache at `access_cache_path`, `select` prompts the user with the choices
+/// and returns the indices of the allowed choices.
+fn request_access_with_cache(
+    access_cache_path: &std::path::Path,
+    kind: AccessKind,
+    requests: &[(String, String)],
+    select: impl FnOnce(&[String]) -> anyhow::Result<Vec<usize>>,
+) -> anyhow::Result<ReadAccess> {
+    let mut access_cache = AccessCache::load(access_cache_path);
+    let allowed_paths = access_cache.get_allowed_paths(kind);
+
+    let paths: Vec<String> = requests.iter().map(|(path, _)| path.clone()).collect();
+    if paths.iter().all(|path| allowed_paths.contains(path)) {
+        // All paths have been previously allowed
+        return Ok(ReadAccess {
+            allowed: paths,
+            denied: vec![],
+        });
+    }
+
+    // Not all paths have been previously allowed so prompt the user
+    let select_choices: Vec<String> = requests
+        .iter()
+        .map(|(path, reason)| format!("{path} - {reason}"))
+        .collect();
+    let selection = select(&select_choices)?;
+
+    let mut access = ReadAccess {
+        allowed: Vec::with_capacity(selection.len()),
+        denied: vec![],
+    };
+    for (index, path) in paths.into_iter().enumerate() {
+        if selection.contains(&index) {
+            allowed_paths.insert(path.clone());
+            access.allowed.push(path);
+        } else {
+            allowed_paths.remove(&path);
+            access.denied.push(path);
+        }
+    }
+
+    access_cache.save(access_cache_path)?;
+    Ok(access)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get a path for an access cache which doesn't exist yet.
+    fn cache_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ask-access-test-{name}-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Request access to paths, choosing the indices in `selection` if the user is prompted.
+    fn request(
+        path: &std::path::Path,
+        kind: AccessKind,
+        paths: &[&str],
+        selection: Option<Vec<usize>>,
+    ) -> ReadAccess {
+        let requests: Vec<(String, String)> = paths
+            .iter()
+            .map(|path| (path.to_string(), "reason".to_string()))
+            .collect();
+        request_access_with_cache(path, kind, &requests, |choices| {
+            assert_eq!(choices.len(), paths.len());
+            Ok(selection.expect("The user should not be prompted"))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn remembers_allowed_paths() {
+        let path = cache_path("remembers");
+        let access = request(&path, AccessKind::Read, &["/a", "/b"], Some(vec![0]));
+        assert_eq!(access.allowed, ["/a"]);
+        assert_eq!(access.denied, ["/b"]);
+        assert_eq!(
+            access.describe_denied(),
+            "The user chose not to allow you to read /b.\n"
+        );
+
+        let access = request(&path, AccessKind::Read, &["/a"], None);
+        assert_eq!(access.allowed, ["/a"]);
+        // The denied path is prompted for again
+        let access = request(&path, AccessKind::Read, &["/a", "/b"], Some(vec![]));
+        assert_eq!(access.denied, ["/a", "/b"]);
+        // And denying a path forgets that it was allowed
+        request(&path, AccessKind::Read, &["/a"], Some(vec![0]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reads_and_searches_are_remembered_separately() {
+        let path = cache_path("separate");
+        request(&path, AccessKind::Read, &["/src"], Some(vec![0]));
+        let access = request(&path, AccessKind::Search, &["/src"], Some(vec![]));
+        assert_eq!(access.denied, ["/src"]);
+
+        request(&path, AccessKind::Search, &["/src"], Some(vec![0]));
+        request(&path, AccessKind::Search, &["/src"], None);
+        request(&path, AccessKind::Read, &["/src"], None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn loads_caches_without_search_paths() {
+        let path = cache_path("old");
+        std::fs::write(&path, r#"{"allowed_read_paths":["/src"]}"#).unwrap();
+        request(&path, AccessKind::Read, &["/src"], None);
+        let access = request(&path, AccessKind::Search, &["/src"], Some(vec![0]));
+        assert_eq!(access.allowed, ["/src"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,12 +1,14 @@
 //! Tool to read files.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, VecDeque},
+    io::{BufRead, Read, Seek, SeekFrom, Write},
     path::Path,
 };
 
 use anyhow::Context;
 
+use super::command_runner::{CommandRunner, CommandSpec, OutputMode, SystemCommandRunner};
 use super::RustTool;
 
 /// Tool to read data from files and to get the contents of directories.
@@ -17,13 +19,674 @@ pub struct ReadFilesTool;
 pub struct ReadFilesToolInput {
     /// A mapping where the key is the filesystem path to read and the value is a short justification for why you want to read that file or directory.
     paths_to_reason_mapping: HashMap<String, String>,
+    /// A mapping where the key is a file path and the value is the byte offset to start reading that file from.
+    /// Use this to read the rest of a file which was truncated, files which aren't in the mapping are read from the start.
+    #[serde(default)]
+    byte_offsets: HashMap<String, u64>,
+    /// A mapping where the key is a file path and the value is which part of the file to read.
+    /// Files which aren't in the mapping are read from the start.
+    #[serde(default)]
+    read_options: HashMap<String, ReadOptions>,
 }
 
-/// A type to store access remembered permissions.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct AccessCache {
-    /// The set of paths which the user has previously allowed read access to.
-    allowed_read_paths: HashSet<String>,
+/// Which part of a file to read, or how much of a directory to list.
+#[derive(serde::Deserialize, schemars::JsonSchema, Debug, Default)]
+pub struct ReadOptions {
+    /// The first line to return, lines are numbered from 1.
+    start_line: Option<usize>,
+    /// The last line to return.
+    end_line: Option<usize>,
+    /// Only return this many lines from the end of the file, for example to read the latest entries of a log.
+    /// This can't be combined with `start_line` or `end_line`.
+    tail_lines: Option<usize>,
+    /// The maximum number of bytes of the file to return, at most 32 KiB are returned regardless.
+    max_bytes: Option<u64>,
+    /// For directories, how many levels to list: 1 only lists the directory's entries, 2 also lists the entries of its
+    /// subdirectories, and so on. Defaults to 1.
+    depth: Option<usize>,
+    /// For directories, the maximum number of entries to list. Defaults to 200, at most 1000 are listed regardless.
+    max_entries: Option<usize>,
+}
+
+/// The maximum number of bytes to read from a file in one invocation.
+const MAX_FILE_BYTES: u64 = 32 * 1024;
+
+/// Files are only read this far to count their lines once the requested lines have been read, so reading the start or
+/// end of a huge file stays fast.
+const MAX_COUNTED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The size of the chunks read backwards from the end of a file for `tail_lines`.
+const TAIL_CHUNK_BYTES: u64 = 8 * 1024;
+
+/// The number of entries to list from a directory by default.
+const DEFAULT_DIRECTORY_ENTRIES: usize = 200;
+
+/// The maximum number of entries to list from a directory in one invocation.
+const MAX_DIRECTORY_ENTRIES: usize = 1000;
+
+/// The maximum depth to list a directory to.
+const MAX_DIRECTORY_DEPTH: usize = 10;
+
+/// The walk stops once this many entries past `max_entries` have been counted, so huge trees aren't walked in full
+/// just to count what was omitted.
+const MAX_OMITTED_ENTRIES: usize = 10_000;
+
+/// The number of bytes at the start of a file which are checked to detect binary files.
+pub(super) const BINARY_SAMPLE_BYTES: usize = 8 * 1024;
+
+/// The magic numbers of common binary file types.
+const BINARY_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xff\xd8\xff", "JPEG image"),
+    (b"GIF87a", "GIF image"),
+    (b"GIF89a", "GIF image"),
+    (b"RIFF", "RIFF media"),
+    (b"%PDF-", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"\x1f\x8b", "gzip archive"),
+    (b"\x7fELF", "ELF executable"),
+    (b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+    (b"MZ", "Windows executable"),
+    (b"\0asm", "WebAssembly module"),
+    (b"SQLite format 3\0", "SQLite database"),
+];
+
+/// Get the type of a binary file from its first bytes, or `None` if the file looks like text.
+///
+/// Files are binary if they contain a NUL byte or have the magic number of a binary type and aren't valid UTF-8.
+pub(super) fn detect_binary(sample: &[u8]) -> Option<&'static str> {
+    let file_type = BINARY_SIGNATURES
+        .iter()
+        .find(|(signature, _)| sample.starts_with(signature))
+        .map(|(_, file_type)| *file_type);
+    // The sample can end in the middle of a character
+    let is_utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if sample.contains(&0) || (file_type.is_some() && !is_utf8) {
+        Some(file_type.unwrap_or("binary data"))
+    } else {
+        None
+    }
+}
+
+/// Check whether a byte is in the middle of a UTF-8 character.
+fn is_continuation_byte(byte: u8) -> bool {
+    (byte & 0b1100_0000) == 0b1000_0000
+}
+
+/// Get the longest prefix of some bytes no longer than a limit which doesn't end in the middle of a character.
+fn truncate_bytes(bytes: &[u8], limit: usize) -> &[u8] {
+    if bytes.len() <= limit {
+        return bytes;
+    }
+    let mut end = limit;
+    while end > 0 && is_continuation_byte(bytes[end]) {
+        end -= 1;
+    }
+    &bytes[..end]
+}
+
+/// A line read from a file.
+struct Line {
+    /// The line number, starting from 1, or counting back from -1 for the last line if the file is too large to count
+    /// its lines.
+    number: i64,
+    /// The content without the line ending.
+    content: Vec<u8>,
+    /// The offset of the byte after the content in the file.
+    end: u64,
+    /// Whether the content starts at the start of the line.
+    is_start_complete: bool,
+    /// Whether the content is the rest of the line.
+    is_complete: bool,
+}
+
+/// The lines read from a file.
+struct ReadLines {
+    lines: VecDeque<Line>,
+    /// The number of lines in the file, `None` if the file is too large to count them.
+    line_count: Option<usize>,
+    /// Whether lines were left out because of the byte limit.
+    is_full: bool,
+}
+
+/// The length of a line read by `read_line_capped`.
+struct LineLength {
+    /// The number of bytes read including the line ending.
+    read: u64,
+    /// The number of bytes of content excluding the line ending.
+    content: u64,
+}
+
+/// Read a line, only keeping the part of its content which starts `skip` bytes into the line and is at most `keep` bytes
+/// long so long lines, such as the lines of minified files, aren't loaded into memory.
+///
+/// Returns `None` at the end of the file.
+fn read_line_capped(
+    reader: &mut impl BufRead,
+    skip: u64,
+    keep: usize,
+    content: &mut Vec<u8>,
+) -> std::io::Result<Option<LineLength>> {
+    content.clear();
+    let mut read = 0;
+    let mut last_byte = None;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok((read > 0).then_some(LineLength {
+                read,
+                content: read,
+            }));
+        }
+        let newline = available.iter().position(|byte| *byte == b'\n');
+        let chunk = &available[..newline.unwrap_or(available.len())];
+        let chunk_length = chunk.len() as u64;
+        let window_start = skip.saturating_sub(read).min(chunk_length);
+        let window_end = (skip + keep as u64)
+            .saturating_sub(read)
+            .clamp(window_start, chunk_length);
+        content.extend_from_slice(
+            &chunk[usize::try_from(window_start).unwrap_or_default()
+                ..usize::try_from(window_end).unwrap_or_default()],
+        );
+        last_byte = chunk.last().copied().or(last_byte);
+        read += chunk_length;
+        let consumed = chunk.len() + usize::from(newline.is_some());
+        reader.consume(consumed);
+
+        if newline.is_some() {
+            // The carriage return of a CRLF line ending isn't content
+            let content_length = read - u64::from(last_byte == Some(b'\r'));
+            content.truncate(
+                usize::try_from(content_length.saturating_sub(skip)).unwrap_or(usize::MAX),
+            );
+            return Ok(Some(LineLength {
+                read: read + 1,
+                content: content_length,
+            }));
+        }
+    }
+}
+
+/// Count the line breaks in the first `length` bytes of a file.
+fn count_line_breaks(file: &mut std::fs::File, length: u64) -> std::io::Result<usize> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = std::io::BufReader::new(file.take(length));
+    let mut count = 0;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(count);
+        }
+        count += available.iter().filter(|byte| **byte == b'\n').count();
+        let consumed = available.len();
+        reader.consume(consumed);
+    }
+}
+
+/// Read the selected lines of a file from its start.
+fn read_lines(
+    reader: &mut impl BufRead,
+    offset: u64,
+    start_line: usize,
+    end_line: usize,
+    max_bytes: usize,
+) -> anyhow::Result<ReadLines> {
+    let mut lines = VecDeque::new();
+    let mut line_count = 0;
+    let mut position = 0;
+    let mut returned_bytes = 0;
+    let mut is_full = false;
+    let mut buffer = Vec::new();
+    loop {
+        let is_selected = !is_full && (start_line..=end_line).contains(&(line_count + 1));
+        // Only count the lines of large files so far once the selected lines have been read
+        if !is_selected && line_count + 1 > start_line && position > MAX_COUNTED_BYTES {
+            return Ok(ReadLines {
+                lines,
+                line_count: None,
+                is_full,
+            });
+        }
+
+        let line_start = position;
+        let skip = offset.saturating_sub(line_start);
+        // A few more bytes than fit are kept to skip a partial character at the offset
+        let keep = if is_selected {
+            max_bytes - returned_bytes + 4
+        } else {
+            0
+        };
+        let Some(length) = read_line_capped(reader, skip, keep, &mut buffer)? else {
+            break;
+        };
+        line_count += 1;
+        position += length.read;
+        if !is_selected || position <= offset {
+            continue;
+        }
+
+        // Start at the offset, skipping the remainder of a character it landed in the middle of
+        let partial = if skip > 0 {
+            buffer
+                .iter()
+                .take(3)
+                .take_while(|byte| is_continuation_byte(**byte))
+                .count()
+        } else {
+            0
+        };
+        let content = &buffer[partial..];
+        let content_start = (line_start + skip + partial as u64).min(line_start + length.content);
+        let remaining = usize::try_from(line_start + length.content - content_start)?;
+        let number = i64::try_from(line_count)?;
+        if returned_bytes + remaining <= max_bytes {
+            returned_bytes += remaining;
+            lines.push_back(Line {
+                number,
+                content: content.to_vec(),
+                end: content_start + remaining as u64,
+                is_start_complete: skip == 0,
+                is_complete: true,
+            });
+        } else {
+            // Return part of a line which doesn't fit by itself so reading makes progress
+            if lines.is_empty() {
+                let content = truncate_bytes(content, max_bytes).to_vec();
+                lines.push_back(Line {
+                    number,
+                    end: content_start + content.len() as u64,
+                    content,
+                    is_start_complete: skip == 0,
+                    is_complete: false,
+                });
+            }
+            is_full = true;
+        }
+    }
+
+    Ok(ReadLines {
+        lines,
+        line_count: Some(line_count),
+        is_full,
+    })
+}
+
+/// Read the last lines of a file by reading backwards from its end.
+fn read_tail(
+    file: &mut std::fs::File,
+    size: u64,
+    tail_lines: usize,
+    max_bytes: usize,
+) -> anyhow::Result<ReadLines> {
+    // Read chunks from the end until there are enough lines or more content than can be returned
+    let mut buffer = Vec::new();
+    let mut start = size;
+    while start > 0 && tail_lines > 0 {
+        let chunk_length = TAIL_CHUNK_BYTES.min(start);
+        start -= chunk_length;
+        let mut chunk = vec![0; usize::try_from(chunk_length)?];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        buffer.splice(0..0, chunk);
+
+        // A line break at the end of the file doesn't start another line
+        let body = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+        let line_breaks = body.iter().filter(|byte| **byte == b'\n').count();
+        if line_breaks >= tail_lines || body.len() - line_breaks > max_bytes + 4 {
+            break;
+        }
+    }
+
+    let line_breaks_before = if start == 0 {
+        Some(0)
+    } else if start <= MAX_COUNTED_BYTES {
+        Some(count_line_breaks(file, start)?)
+    } else {
+        None
+    };
+
+    let body = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+    let mut lines = VecDeque::new();
+    let mut line_start = start;
+    if !body.is_empty() {
+        for (index, content) in body.split(|byte| *byte == b'\n').enumerate() {
+            let end = line_start + content.len() as u64;
+            let content = content.strip_suffix(b"\r").unwrap_or(content);
+            lines.push_back(Line {
+                number: i64::try_from(index)?,
+                content: content.to_vec(),
+                end: line_start + content.len() as u64,
+                // The first line starts before the buffer unless the buffer is the start of the file
+                is_start_complete: index > 0 || start == 0,
+                is_complete: true,
+            });
+            line_start = end + 1;
+        }
+    }
+    let buffered_lines = lines.len();
+    // The last line only ends with a line break if it isn't the last line
+    let mut last_byte = [b'\n'];
+    if size > 0 {
+        file.seek(SeekFrom::Start(size - 1))?;
+        file.read_exact(&mut last_byte)?;
+    }
+    let buffered_line_breaks = buffer.iter().filter(|byte| **byte == b'\n').count();
+    let line_count = line_breaks_before
+        .map(|line_breaks| line_breaks + buffered_line_breaks + usize::from(last_byte[0] != b'\n'));
+    for line in &mut lines {
+        line.number = match line_breaks_before {
+            Some(line_breaks) => line.number + i64::try_from(line_breaks)? + 1,
+            None => line.number - i64::try_from(buffered_lines)?,
+        };
+    }
+
+    // Keep the end of the file within the limits
+    let mut returned_bytes: usize = lines.iter().map(|line| line.content.len()).sum();
+    while lines.len() > tail_lines || (returned_bytes > max_bytes && lines.len() > 1) {
+        if let Some(removed) = lines.pop_front() {
+            returned_bytes -= removed.content.len();
+        }
+    }
+    // A single line of a tail can still be too long, keep its end
+    if let Some(line) = lines.front_mut() {
+        if line.content.len() > max_bytes {
+            let mut start = line.content.len() - max_bytes;
+            while start < line.content.len() && is_continuation_byte(line.content[start]) {
+                start += 1;
+            }
+            line.content.drain(..start);
+            line.is_start_complete = false;
+        }
+    }
+
+    Ok(ReadLines {
+        lines,
+        line_count,
+        is_full: false,
+    })
+}
+
+/// Read part of a file, formatted for the LLM with line numbers.
+fn read_file(file_path: &Path, offset: u64, options: &ReadOptions) -> anyhow::Result<String> {
+    if options.tail_lines.is_some() && (options.start_line.is_some() || options.end_line.is_some())
+    {
+        anyhow::bail!("`tail_lines` can't be combined with `start_line` or `end_line`");
+    }
+    if options.tail_lines.is_some() && offset > 0 {
+        anyhow::bail!("`tail_lines` can't be combined with a byte offset");
+    }
+    let start_line = options.start_line.unwrap_or(1).max(1);
+    let end_line = options.end_line.unwrap_or(usize::MAX);
+    if end_line < start_line {
+        anyhow::bail!("`end_line` {end_line} is before `start_line` {start_line}");
+    }
+    let max_bytes = usize::try_from(
+        options
+            .max_bytes
+            .map_or(MAX_FILE_BYTES, |max| max.min(MAX_FILE_BYTES)),
+    )?;
+
+    let mut file = std::fs::File::open(file_path)?;
+    let size = file.metadata()?.len();
+    let mut reader = std::io::BufReader::with_capacity(BINARY_SAMPLE_BYTES, &mut file);
+    if let Some(file_type) = detect_binary(reader.fill_buf()?) {
+        return Ok(format!(
+            "{} is a binary file ({file_type}, {size} bytes) so its content was not returned.\n",
+            file_path.display()
+        ));
+    }
+
+    let ReadLines {
+        lines,
+        line_count,
+        is_full,
+    } = match options.tail_lines {
+        Some(tail_lines) => {
+            drop(reader);
+            read_tail(&mut file, size, tail_lines, max_bytes)?
+        }
+        None => read_lines(&mut reader, offset, start_line, end_line, max_bytes)?,
+    };
+
+    let mut is_lossy = false;
+    let width = lines
+        .iter()
+        .map(|line| line.number.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let mut result = match line_count {
+        Some(line_count) => format!(
+            "<file path=\"{}\" size=\"{size}\" lines=\"{line_count}\">\n",
+            file_path.display()
+        ),
+        None => format!("<file path=\"{}\" size=\"{size}\">\n", file_path.display()),
+    };
+    for line in &lines {
+        let content = String::from_utf8_lossy(&line.content);
+        is_lossy |= matches!(content, std::borrow::Cow::Owned(_));
+        result.push_str(&format!("{:>width$}\t{content}\n", line.number));
+    }
+    result.push_str("</file>\n");
+
+    if is_lossy {
+        result.push_str(&format!(
+            "{} is not valid UTF-8, the invalid bytes were replaced with U+FFFD.\n",
+            file_path.display()
+        ));
+    }
+    let of_the_file = |plural: &str| match line_count {
+        Some(line_count) => format!("of the file's {line_count} {plural}"),
+        None => "of the file".to_string(),
+    };
+    match (lines.front(), lines.back()) {
+        (Some(first), Some(last)) => {
+            let is_whole_file = first.number == 1
+                && line_count == usize::try_from(last.number).ok()
+                && first.is_start_complete
+                && last.is_complete;
+            if !is_whole_file {
+                if first.number == last.number {
+                    let part = if first.is_start_complete && last.is_complete {
+                        ""
+                    } else {
+                        "part of "
+                    };
+                    result.push_str(&format!(
+                        "Only {part}line {} {} was returned.",
+                        first.number,
+                        of_the_file("line(s)")
+                    ));
+                } else {
+                    result.push_str(&format!(
+                        "Only lines {} to {} {} were returned.",
+                        first.number,
+                        last.number,
+                        of_the_file("lines")
+                    ));
+                }
+                if line_count.is_none() {
+                    result.push_str(" The file is too large to count its lines");
+                    result.push_str(if last.number < 0 {
+                        ", so the lines are numbered back from -1 for the last line."
+                    } else {
+                        "."
+                    });
+                }
+                if !last.is_complete {
+                    result.push_str(&format!(
+                        " Line {} was cut short, set the byte offset for this path to {} in `byte_offsets` to read more.",
+                        last.number, last.end
+                    ));
+                } else if is_full
+                    && usize::try_from(last.number).is_ok_and(|number| number < end_line)
+                {
+                    result.push_str(&format!(
+                        " Set `start_line` to {} in `read_options` for this path to read more.",
+                        last.number + 1
+                    ));
+                }
+                result.push('\n');
+            }
+        }
+        _ => match line_count {
+            Some(0) => {}
+            Some(line_count) => result.push_str(&format!(
+                "No lines were returned since the file has {line_count} lines.\n"
+            )),
+            None => result.push_str("No lines were returned.\n"),
+        },
+    }
+    Ok(result)
+}
+
+/// Describe a directory entry for a listing, indented by its depth.
+fn describe_entry(entry: &ignore::DirEntry) -> String {
+    let indent = "  ".repeat(entry.depth().saturating_sub(1));
+    let name = entry.file_name().to_string_lossy();
+    let metadata = match entry.metadata() {
+        Ok(metadata) => metadata,
+        Err(e) => return format!("{indent}{name} (could not read metadata: {e})"),
+    };
+    let modified = metadata
+        .modified()
+        .map(|modified| {
+            chrono::DateTime::<chrono::Local>::from(modified)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    if entry.path_is_symlink() {
+        let target = std::fs::read_link(entry.path())
+            .map_or_else(|_| "?".to_string(), |target| target.display().to_string());
+        format!("{indent}{name} -> {target}")
+    } else if metadata.is_dir() {
+        format!("{indent}{name}/  modified {modified}")
+    } else {
+        format!(
+            "{indent}{name}  {} bytes  modified {modified}",
+            metadata.len()
+        )
+    }
+}
+
+/// List a directory as a tree, respecting `.gitignore` and `.ignore` files and leaving out the entries the tool policy
+/// denies.
+fn list_directory(
+    directory: &Path,
+    options: &ReadOptions,
+    policy: &super::policy::PathFilter,
+) -> anyhow::Result<String> {
+    let depth = options.depth.unwrap_or(1).clamp(1, MAX_DIRECTORY_DEPTH);
+    let max_entries = options
+        .max_entries
+        .unwrap_or(DEFAULT_DIRECTORY_ENTRIES)
+        .min(MAX_DIRECTORY_ENTRIES);
+
+    // Denied paths are matched against the resolved path of each entry, and denied directories aren't walked
+    let denied_entries = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let walk_denied_entries = denied_entries.clone();
+    let walk_directory = directory.to_path_buf();
+    let resolved_directory = super::policy::resolve_path(directory);
+    let policy = policy.clone();
+    let walk = ignore::WalkBuilder::new(directory)
+        .max_depth(Some(depth))
+        // Hidden files such as `.env` are often what the user is asking about
+        .hidden(false)
+        .filter_entry(move |entry| {
+            if entry.file_name() == ".git" {
+                return false;
+            }
+            let relative_path = entry
+                .path()
+                .strip_prefix(&walk_directory)
+                .unwrap_or(entry.path());
+            if entry.depth() > 0 && policy.is_denied(&resolved_directory.join(relative_path)) {
+                walk_denied_entries.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                return false;
+            }
+            true
+        })
+        .sort_by_file_name(std::ffi::OsStr::cmp)
+        .build();
+
+    let mut listed = vec![];
+    let mut omitted_directories = 0;
+    let mut omitted_files = 0;
+    let mut unexpanded_directories = 0;
+    let mut is_count_capped = false;
+    let mut errors = vec![];
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+        // The directory itself
+        if entry.depth() == 0 {
+            continue;
+        }
+
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
+        if listed.len() >= max_entries {
+            if omitted_directories + omitted_files >= MAX_OMITTED_ENTRIES {
+                is_count_capped = true;
+                break;
+            }
+            if is_dir {
+                omitted_directories += 1;
+            } else {
+                omitted_files += 1;
+            }
+            continue;
+        }
+        if is_dir && entry.depth() == depth {
+            unexpanded_directories += 1;
+        }
+        listed.push(describe_entry(&entry));
+    }
+
+    let mut result = format!(
+        "<directory path=\"{}\">\n{}{}</directory>\n",
+        directory.display(),
+        listed.join("\n"),
+        if listed.is_empty() { "" } else { "\n" }
+    );
+    if is_count_capped {
+        result.push_str(&format!(
+            "Only the first {max_entries} entries were listed, more than {MAX_OMITTED_ENTRIES} entries were omitted. List subdirectories separately or increase `max_entries` in `read_options` to see more.\n"
+        ));
+    } else if omitted_directories + omitted_files > 0 {
+        result.push_str(&format!(
+            "Only the first {max_entries} entries were listed, {omitted_directories} directories and {omitted_files} files were omitted. List subdirectories separately or increase `max_entries` in `read_options` to see more.\n"
+        ));
+    }
+    let denied_entries = denied_entries.load(std::sync::atomic::Ordering::Relaxed);
+    if denied_entries > 0 {
+        result.push_str(&format!(
+            "{denied_entries} entries were not listed since the tool policy denies reading them.\n"
+        ));
+    }
+    if unexpanded_directories > 0 && depth < MAX_DIRECTORY_DEPTH {
+        result.push_str(&format!(
+            "{unexpanded_directories} directories at depth {depth} were not expanded, increase `depth` in `read_options` to list their contents.\n"
+        ));
+    }
+    if !errors.is_empty() {
+        result.push_str(&format!(
+            "Some entries could not be listed:\n{}\n",
+            errors.join("\n")
+        ));
+    }
+    Ok(result)
 }
 
 impl RustTool for ReadFilesTool {
@@ -36,108 +699,72 @@ impl RustTool for ReadFilesTool {
     fn get_description(&self) -> String {
         r" Read the content of one or more file paths from the user's computer.
             The user will be prompted to accept or deny the read request to preserve their privacy.
-            For paths that are files the tool will return the content of the files as a XML `file` tags with a `path` attribute to determine which file the content is from.
-            For paths that are directories the tool will return a list of the files and subdirectories in the directory using XML `directory` tags with a `path` attribute to determine which directory the content is from.
+            For paths that are files the tool will return the content of the files as a XML `file` tags with a `path` attribute to determine which file the content is from, and `size` and `lines` attributes with the file's size in bytes and its number of lines.
+            Each line of a file starts with its line number and a tab, the line numbers aren't part of the file.
+            For paths that are directories the tool will return a tree of the files and subdirectories in the directory using XML `directory` tags with a `path` attribute to determine which directory the content is from.
+            Directory trees include the size and modification time of each entry, leave out files ignored by `.gitignore` and `.ignore` files, and list one level by default, use `depth` in `read_options` to list more levels in one call.
+            At most 32 KiB is returned from each file, use `read_options` to read specific lines or the end of a file and `byte_offsets` to continue from where a line was cut short.
+            The content of binary files isn't returned, their type and size are returned instead.
         "
         .trim()
         .to_string()
     }
 
-    async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
-        let access_cache_path = super::get_cache_dir_for_tool(self.as_ref())?.join("access.json");
-        let mut access_cache: AccessCache = AccessCache {
-            allowed_read_paths: HashSet::with_capacity(2),
-        };
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        super::policy::RuleField::PATHS
+    }
 
-        if let Ok(file) = std::fs::File::open(&access_cache_path) {
-            match serde_json::from_reader(file) {
-                Ok(cache) => access_cache = cache,
-                Err(e) => {
-                    tracing::warn!(
-                        "Failed to read access cache at {}: {}",
-                        access_cache_path.display(),
-                        e
-                    );
-                }
-            }
-        } else {
-            tracing::info!("No access cache found at {}", access_cache_path.display());
-        }
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        super::policy::check_paths(
+            input.paths_to_reason_mapping.keys().map(String::as_str),
+            rule,
+        )
+    }
 
+    async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
         let current_dir = std::env::current_dir().context("Failed to get the current directory")?;
-        let mut paths = Vec::with_capacity(input.paths_to_reason_mapping.len());
-        let mut files_to_read = Vec::with_capacity(input.paths_to_reason_mapping.len());
-        let mut select_choices: Vec<_> = Vec::with_capacity(input.paths_to_reason_mapping.len());
-        let mut response = String::with_capacity(128);
-        for (path, reason) in &input.paths_to_reason_mapping {
-            let full_path = current_dir.join(path).to_string_lossy().to_string();
-            select_choices.push(format!("{} - {reason}", &full_path));
-            paths.push(full_path);
-        }
-
-        if paths
-            .iter()
-            .all(|path| access_cache.allowed_read_paths.contains(path))
-        {
-            // All paths have been previously allowed
-            files_to_read.extend(paths);
-        } else {
-            // Not all paths have been previously allowed so prompt the user
-            let mut disallowed_files: HashSet<_> = paths.iter().cloned().collect();
-            // Create a default selection of all paths (all allowed by default
-            let defaults: Vec<bool> = paths.iter().map(|_| true).collect();
-            let selection = dialoguer::MultiSelect::new()
-                .with_prompt("Files to allow the LLM to read")
-                .items(&select_choices)
-                .defaults(&defaults)
-                .interact()
-                .context("From multi-select")?;
-
-            for file_index in selection {
-                let path = paths[file_index].clone();
-                disallowed_files.remove(&path);
-                access_cache.allowed_read_paths.insert(path.clone());
-                files_to_read.push(path);
+        let mut requests = Vec::with_capacity(input.paths_to_reason_mapping.len());
+        let mut byte_offsets = HashMap::with_capacity(input.byte_offsets.len());
+        let mut read_options = HashMap::with_capacity(input.read_options.len());
+        let mut input_read_options = input.read_options;
+        for (path, reason) in input.paths_to_reason_mapping {
+            let full_path = current_dir.join(&path).to_string_lossy().to_string();
+            if let Some(offset) = input.byte_offsets.get(&path) {
+                byte_offsets.insert(full_path.clone(), *offset);
             }
-
-            for file in disallowed_files {
-                response.push_str(&format!(
-                    "The user chose not to allow you to read {file}.\n"
-                ));
-                access_cache.allowed_read_paths.remove(&file);
+            if let Some(options) = input_read_options.remove(&path) {
+                read_options.insert(full_path.clone(), options);
             }
+            requests.push((full_path, reason));
         }
 
-        for file_path_string in files_to_read {
+        let config = productivity_config::Config::get_or_default()?;
+        // The tool policy checks the listed directories, entries under them can still be denied
+        let policy = super::policy::PathFilter::new(config.tool_policy.rules.get(&self.get_name()))
+            .map_err(anyhow::Error::msg)?;
+
+        let access =
+            super::file_access::request_access(super::file_access::AccessKind::Read, &requests)?;
+        let mut response = access.describe_denied();
+        for file_path_string in access.allowed {
             let file_path = Path::new(&file_path_string);
+            let options = read_options.remove(&file_path_string).unwrap_or_default();
             let read_result = if file_path.is_dir() {
-                // Attempt to read the directory
-                std::fs::read_dir(file_path)
-                    .and_then(|dir| {
-                        dir.map(|entry| {
-                            entry.map(|entry| {
-                                let path = entry.path();
-                                if path.is_dir() {
-                                    format!("{}/", path.display())
-                                } else {
-                                    format!("{}", path.display())
-                                }
-                            })
-                        })
-                        .collect::<Result<Vec<_>, _>>()
-                    })
-                    .map(|result_vec| {
-                        format!(
-                            "<directory path=\"{}\">\n{}\n</directory>\n",
-                            file_path.display(),
-                            result_vec.join("\n")
-                        )
-                    })
+                list_directory(file_path, &options, &policy)
             } else {
                 // Attempt to read the file
-                std::fs::read_to_string(file_path).map(|content| {
-                    format!("<file path=\"{}\">{content}</file>\n", file_path.display())
-                })
+                read_file(
+                    file_path,
+                    byte_offsets
+                        .get(&file_path_string)
+                        .copied()
+                        .unwrap_or_default(),
+                    &options,
+                )
             };
 
             match read_result {
@@ -145,31 +772,28 @@ impl RustTool for ReadFilesTool {
                     response.push_str(&content);
                 }
                 Err(e) => {
-                    response.push_str(&format!("Could not read {}: {e}\n", file_path.display()));
+                    response.push_str(&format!("Could not read {}: {e:#}\n", file_path.display()));
                 }
             }
         }
 
-        // Write the access cache back to disk
-        let access_cache_file = std::fs::File::create(&access_cache_path).with_context(|| {
-            format!(
-                "Failed to create the access cache file at {}",
-                access_cache_path.display()
-            )
-        })?;
-        serde_json::to_writer(access_cache_file, &access_cache).with_context(|| {
-            format!(
-                "Failed to write the access cache file at {}",
-                access_cache_path.display()
-            )
-        })?;
-
         Ok(response)
     }
 }
 
 /// Tool to write data to files.
-pub struct WriteFilesTool;
+pub struct WriteFilesTool {
+    /// Runs the user's editor.
+    runner: std::sync::Arc<dyn CommandRunner>,
+}
+
+impl WriteFilesTool {
+    pub fn new() -> Self {
+        WriteFilesTool {
+            runner: std::sync::Arc::new(SystemCommandRunner),
+        }
+    }
+}
 
 /// Input to the write files tool.
 #[derive(serde::Deserialize, schemars::JsonSchema, Debug)]
@@ -187,30 +811,52 @@ impl RustTool for WriteFilesTool {
 
     fn get_description(&self) -> String {
         r" Write to one or more file paths on the user's computer.
-            The user will be prompted to accept or deny each write.
+            The user will be shown a diff against the existing file, or the content of a new file, and prompted to accept or deny each write.
+            The user can also edit the content before it's written, in which case the result will include the changes they made.
             Intermediate directories will be created if they don't exist.
         "
         .trim()
         .to_string()
     }
 
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        super::policy::RuleField::PATHS
+    }
+
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        super::policy::check_paths(input.paths_to_content.keys().map(String::as_str), rule)
+    }
+
     async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
+        let config = productivity_config::Config::get_or_default()?;
+        let color_mode = crate::color_mode::ColorMode::detect();
+        let printer = crate::response_parsing::Printer::new(
+            crate::color_mode::load_theme(config.ask_theme.as_deref())?,
+            color_mode,
+        );
         let mut response = vec![];
         let cwd = std::env::current_dir().context("Failed to get the current directory")?;
         for (file, content) in input.paths_to_content {
             let file_path = cwd.join(file);
-            // TODO show the file content being written and show a diff if the file already exists
-            let allow_write = dialoguer::Confirm::new()
-                .with_prompt(format!("Write to {}?", file_path.display()))
-                .interact()
-                .context("From confirm")?;
-            if !allow_write {
+            let reviewed_content = review_write(
+                self.runner.as_ref(),
+                &printer,
+                color_mode != crate::color_mode::ColorMode::Plain,
+                &file_path,
+                &content,
+            )?;
+            crate::audit::record_approval(reviewed_content.is_some());
+            let Some(reviewed_content) = reviewed_content else {
                 response.push(format!(
                     "The user said they did not want to you to write to {}",
                     file_path.display()
                 ));
                 continue;
-            }
+            };
 
             let parent_dir = file_path
                 .parent()
@@ -222,10 +868,10 @@ impl RustTool for WriteFilesTool {
                 )
             })?;
 
-            let write_result = std::fs::write(&file_path, content);
+            let write_result = std::fs::write(&file_path, &reviewed_content);
             match write_result {
                 Ok(()) => {
-                    response.push(format!("Wrote to {}", file_path.display()));
+                    response.push(describe_write(&file_path, &content, &reviewed_content));
                 }
                 Err(e) => {
                     response.push(format!("Could not write to {}: {e}", file_path.display()));
@@ -235,3 +881,627 @@ impl RustTool for WriteFilesTool {
         Ok(response.join("\n"))
     }
 }
+
+/// Describe a successful write for the LLM, including the changes the user made to the proposed content.
+fn describe_write(file_path: &Path, proposed: &str, written: &str) -> String {
+    if proposed == written {
+        format!("Wrote to {}", file_path.display())
+    } else {
+        format!(
+            "The user modified the content before it was written to {}, the changes they made to your content were:\n{}",
+            file_path.display(),
+            format_diff(file_path, proposed, written, false)
+        )
+    }
+}
+
+/// Format a unified diff between two versions of a file, colored for the terminal if `colored` is set.
+fn format_diff(file_path: &Path, old: &str, new: &str, colored: bool) -> String {
+    let path = file_path.display().to_string();
+    let diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&path, &path)
+        .to_string();
+    if !colored {
+        return diff;
+    }
+
+    diff.lines()
+        .map(|line| {
+            let styled = console::style(line);
+            let styled = if line.starts_with("---") || line.starts_with("+++") {
+                styled.bold()
+            } else if line.starts_with("@@") {
+                styled.cyan()
+            } else if line.starts_with('-') {
+                styled.red()
+            } else if line.starts_with('+') {
+                styled.green()
+            } else {
+                styled
+            };
+            format!("{styled}\n")
+        })
+        .collect()
+}
+
+/// Show what writing content to a file would change, the diff against the file if it exists or the highlighted content
+/// if it doesn't.
+fn print_write_preview(
+    printer: &crate::response_parsing::Printer,
+    colored: bool,
+    file_path: &Path,
+    content: &str,
+) {
+    if !file_path.exists() {
+        println!("{} is a new file:", file_path.display());
+        // Files such as `Dockerfile` are recognized by their name
+        let language = file_path
+            .extension()
+            .or_else(|| file_path.file_name())
+            .map(|language| language.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for line in printer.highlight(&language, content) {
+            println!("{line}");
+        }
+        return;
+    }
+
+    match std::fs::read_to_string(file_path) {
+        Ok(existing) if existing == content => {
+            println!("{} already has this content", file_path.display());
+        }
+        Ok(existing) => print!("{}", format_diff(file_path, &existing, content, colored)),
+        Err(e) => println!(
+            "{} will be overwritten, its current content can't be shown: {e}",
+            file_path.display()
+        ),
+    }
+}
+
+/// Split an editor command such as `code --wait` into its arguments, `None` if there's no command.
+fn parse_editor(editor: &str) -> Option<Vec<String>> {
+    let editor = editor.trim();
+    let arguments = shlex::split(editor).unwrap_or_else(|| vec![editor.to_string()]);
+    if arguments.iter().all(|argument| argument.trim().is_empty()) {
+        None
+    } else {
+        Some(arguments)
+    }
+}
+
+/// Let the user edit content with an editor command using a temporary file with the same extension as the file being
+/// written.
+fn edit_content(
+    runner: &dyn CommandRunner,
+    editor: &[String],
+    file_path: &Path,
+    content: &str,
+) -> anyhow::Result<String> {
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // The file is created with permissions only the user can read since the content can be sensitive
+    let mut temporary_file = tempfile::Builder::new()
+        .prefix("ask-")
+        .suffix(&format!("-{file_name}"))
+        .tempfile()
+        .context("Failed to create a temporary file to edit")?;
+    temporary_file
+        .write_all(content.as_bytes())
+        .and_then(|()| temporary_file.flush())
+        .with_context(|| format!("Failed to write {}", temporary_file.path().display()))?;
+
+    let output = runner.run(
+        &CommandSpec::new(&editor[0])
+            .args(&editor[1..])
+            .arg(temporary_file.path().to_string_lossy())
+            .output_mode(OutputMode::Inherit),
+    );
+    // Editors can replace the file rather than writing to it so it's read by its path
+    let edited = std::fs::read_to_string(temporary_file.path())
+        .with_context(|| format!("Failed to read {}", temporary_file.path().display()));
+
+    if !output?.success() {
+        anyhow::bail!("{} exited unsuccessfully", editor[0]);
+    }
+    edited
+}
+
+/// Show the user what writing a file would change and let them accept, reject, or edit the content.
+///
+/// Returns the content to write, or `None` if the user rejected the write.
+fn review_write(
+    runner: &dyn CommandRunner,
+    printer: &crate::response_parsing::Printer,
+    colored: bool,
+    file_path: &Path,
+    content: &str,
+) -> anyhow::Result<Option<String>> {
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .and_then(|editor| parse_editor(&editor));
+    // Not writing is first so pressing enter without choosing doesn't overwrite a file
+    let mut choices = vec!["Don't write the file", "Write the file"];
+    if editor.is_some() {
+        choices.push("Edit the content in $EDITOR");
+    }
+
+    let mut content = content.to_string();
+    loop {
+        let choice = crate::status::suspend(|| {
+            print_write_preview(printer, colored, file_path, &content);
+            dialoguer::Select::new()
+                .with_prompt(format!("Write to {}?", file_path.display()))
+                .items(&choices)
+                .default(0)
+                .interact()
+        })
+        .context("From select")?;
+
+        match (choice, &editor) {
+            (1, _) => return Ok(Some(content)),
+            (2, Some(editor)) => match edit_content(runner, editor, file_path, &content) {
+                Ok(edited) => content = edited,
+                Err(e) => eprintln!("Could not edit the content: {e:#}"),
+            },
+            _ => return Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::FakeCommandRunner;
+    use crate::tools::policy::PathFilter;
+
+    /// Write a file for a test and read it.
+    fn read(name: &str, content: &[u8], offset: u64, options: &ReadOptions) -> (String, String) {
+        let path =
+            std::env::temp_dir().join(format!("ask-read-test-{name}-{}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let result = read_file(&path, offset, options);
+        std::fs::remove_file(&path).unwrap();
+        (path.display().to_string(), result.unwrap())
+    }
+
+    /// Get the lines of a result after the `file` tag.
+    fn body(result: &str) -> &str {
+        &result[result.find(">\n").unwrap() + 2..]
+    }
+
+    #[test]
+    fn reads_whole_files() {
+        let (path, result) = read("whole", b"a\nb", 0, &ReadOptions::default());
+        assert_eq!(
+            result,
+            format!("<file path=\"{path}\" size=\"3\" lines=\"2\">\n1\ta\n2\tb\n</file>\n")
+        );
+
+        let (path, result) = read("empty", b"", 0, &ReadOptions::default());
+        assert_eq!(
+            result,
+            format!("<file path=\"{path}\" size=\"0\" lines=\"0\">\n</file>\n")
+        );
+    }
+
+    #[test]
+    fn reads_line_ranges() {
+        let content: String = (1..=12).map(|number| format!("line{number}\n")).collect();
+        let options = ReadOptions {
+            start_line: Some(9),
+            end_line: Some(10),
+            ..ReadOptions::default()
+        };
+        let (_, result) = read("range", content.as_bytes(), 0, &options);
+        assert_eq!(
+            body(&result),
+            " 9\tline9\n10\tline10\n</file>\nOnly lines 9 to 10 of the file's 12 lines were returned.\n"
+        );
+    }
+
+    #[test]
+    fn reads_tails() {
+        let options = ReadOptions {
+            tail_lines: Some(2),
+            ..ReadOptions::default()
+        };
+        let (_, result) = read("tail", b"a\r\nb\r\nc\r\n", 0, &options);
+        assert_eq!(
+            body(&result),
+            "2\tb\n3\tc\n</file>\nOnly lines 2 to 3 of the file's 3 lines were returned.\n"
+        );
+
+        // Longer than a chunk so the lines before the tail are counted
+        let content: String = (1..=3000)
+            .map(|number| format!("line {number}\n"))
+            .collect();
+        let (_, result) = read("long-tail", content.as_bytes(), 0, &options);
+        assert!(result.contains("lines=\"3000\""));
+        assert_eq!(
+            body(&result),
+            "2999\tline 2999\n3000\tline 3000\n</file>\nOnly lines 2999 to 3000 of the file's 3000 lines were returned.\n"
+        );
+
+        let options = ReadOptions {
+            tail_lines: Some(0),
+            ..ReadOptions::default()
+        };
+        let (_, result) = read("no-tail", b"a\nb\n", 0, &options);
+        assert_eq!(
+            body(&result),
+            "</file>\nNo lines were returned since the file has 2 lines.\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_end_of_long_tail_lines() {
+        let options = ReadOptions {
+            tail_lines: Some(1),
+            max_bytes: Some(4),
+            ..ReadOptions::default()
+        };
+        let (_, result) = read("long-tail-line", b"first\n0123456789", 0, &options);
+        assert_eq!(
+            body(&result),
+            "2\t6789\n</file>\nOnly part of line 2 of the file's 2 line(s) was returned.\n"
+        );
+    }
+
+    #[test]
+    fn limits_bytes() {
+        let options = ReadOptions {
+            max_bytes: Some(15),
+            ..ReadOptions::default()
+        };
+        let (_, result) = read("max-bytes", b"0123456789\nabcdefghij\n", 0, &options);
+        assert_eq!(
+            body(&result),
+            "1\t0123456789\n</file>\nOnly line 1 of the file's 2 line(s) was returned. Set `start_line` to 2 in `read_options` for this path to read more.\n"
+        );
+    }
+
+    #[test]
+    fn continues_long_lines_from_byte_offsets() {
+        let options = ReadOptions {
+            max_bytes: Some(2),
+            ..ReadOptions::default()
+        };
+        // The limit falls in the middle of `é`
+        let (_, result) = read("offset", "aé\nb\n".as_bytes(), 0, &options);
+        assert_eq!(
+            body(&result),
+            "1\ta\n</file>\nOnly part of line 1 of the file's 2 line(s) was returned. Line 1 was cut short, set the byte offset for this path to 1 in `byte_offsets` to read more.\n"
+        );
+
+        let (_, result) = read(
+            "offset-continued",
+            "aé\nb\n".as_bytes(),
+            1,
+            &ReadOptions::default(),
+        );
+        assert_eq!(
+            body(&result),
+            "1\té\n2\tb\n</file>\nOnly lines 1 to 2 of the file's 2 lines were returned.\n"
+        );
+
+        // An offset in the middle of a character skips the rest of it
+        let (_, result) = read(
+            "offset-partial",
+            "aéc\n".as_bytes(),
+            2,
+            &ReadOptions::default(),
+        );
+        assert!(body(&result).starts_with("1\tc\n</file>\n"));
+    }
+
+    #[test]
+    fn only_returns_the_start_of_huge_lines() {
+        let mut content = vec![b'x'; 1024 * 1024];
+        content.push(b'\n');
+        let (_, result) = read("huge-line", &content, 0, &ReadOptions::default());
+        assert_eq!(
+            body(&result).lines().next().unwrap(),
+            format!("1\t{}", "x".repeat(32 * 1024))
+        );
+        assert!(result.ends_with(
+            "Line 1 was cut short, set the byte offset for this path to 32768 in `byte_offsets` to read more.\n"
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_utf8() {
+        let (path, result) = read("lossy", b"caf\xe9\n", 0, &ReadOptions::default());
+        assert_eq!(
+            body(&result),
+            format!("1\tcaf\u{FFFD}\n</file>\n{path} is not valid UTF-8, the invalid bytes were replaced with U+FFFD.\n")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let path = Path::new("/nonexistent");
+        let tail_and_start = ReadOptions {
+            tail_lines: Some(1),
+            start_line: Some(1),
+            ..ReadOptions::default()
+        };
+        assert!(read_file(path, 0, &tail_and_start).is_err());
+        let backwards = ReadOptions {
+            start_line: Some(2),
+            end_line: Some(1),
+            ..ReadOptions::default()
+        };
+        assert_eq!(
+            read_file(path, 0, &backwards).unwrap_err().to_string(),
+            "`end_line` 1 is before `start_line` 2"
+        );
+        let tail = ReadOptions {
+            tail_lines: Some(1),
+            ..ReadOptions::default()
+        };
+        assert!(read_file(path, 1, &tail).is_err());
+    }
+
+    #[test]
+    fn detects_binary_files() {
+        for (sample, expected) in [
+            (&b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"[..], Some("PNG image")),
+            (b"\x7fELF\x02\x01\x01\0", Some("ELF executable")),
+            (b"PK\x03\x04\x14\0", Some("ZIP archive")),
+            (b"text with a \0 byte", Some("binary data")),
+            (b"\xff\xd8\xff\xe0", Some("JPEG image")),
+            // Text which happens to start with a magic number
+            (b"MZ is a text file", None),
+            (b"plain text", None),
+            // The sample can end in the middle of a character
+            (&"caf\u{e9}".as_bytes()[..4], None),
+        ] {
+            assert_eq!(detect_binary(sample), expected, "detecting {sample:?}");
+        }
+
+        let (path, result) = read(
+            "binary",
+            b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+            0,
+            &ReadOptions::default(),
+        );
+        assert_eq!(
+            result,
+            format!(
+                "{path} is a binary file (PNG image, 16 bytes) so its content was not returned.\n"
+            )
+        );
+    }
+
+    /// Create a directory tree for a test, directories are the paths ending in `/`.
+    fn create_tree(name: &str, paths: &[&str]) -> std::path::PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("ask-list-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        for path in paths {
+            if path.ends_with('/') {
+                std::fs::create_dir_all(root.join(path)).unwrap();
+            } else {
+                std::fs::write(root.join(path), "content").unwrap();
+            }
+        }
+        root
+    }
+
+    /// Get the indented names of the entries in a listing, without their sizes and modification times.
+    fn entry_names(result: &str) -> Vec<&str> {
+        body(result)
+            .lines()
+            .take_while(|line| *line != "</directory>")
+            .map(|line| {
+                let name_start = line.len() - line.trim_start().len();
+                line[name_start..]
+                    .find("  ")
+                    .map_or(line, |end| &line[..name_start + end])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lists_directories_to_a_depth() {
+        let root = create_tree("depth", &["b/c/d/", "b/c/file", "a"]);
+        let shallow =
+            list_directory(&root, &ReadOptions::default(), &PathFilter::default()).unwrap();
+        let deep = list_directory(
+            &root,
+            &ReadOptions {
+                depth: Some(2),
+                ..ReadOptions::default()
+            },
+            &PathFilter::default(),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(shallow.starts_with(&format!("<directory path=\"{}\">\n", root.display())));
+        assert_eq!(entry_names(&shallow), ["a", "b/"]);
+        assert!(shallow.ends_with(
+            "</directory>\n1 directories at depth 1 were not expanded, increase `depth` in `read_options` to list their contents.\n"
+        ));
+        assert_eq!(entry_names(&deep), ["a", "b/", "  c/"]);
+        assert!(deep.contains("1 directories at depth 2 were not expanded"));
+        assert!(body(&shallow).starts_with("a  7 bytes  modified "));
+        assert!(body(&shallow).contains("\nb/  modified "));
+    }
+
+    #[test]
+    fn respects_ignore_files() {
+        let root = create_tree(
+            "ignore",
+            &[
+                ".git/",
+                ".gitignore",
+                ".env",
+                "target/",
+                "main.rs",
+                "notes.txt",
+            ],
+        );
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(root.join(".ignore"), "*.txt\n").unwrap();
+        let result =
+            list_directory(&root, &ReadOptions::default(), &PathFilter::default()).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            entry_names(&result),
+            [".env", ".gitignore", ".ignore", "main.rs"]
+        );
+    }
+
+    #[test]
+    fn caps_listed_entries() {
+        let root = create_tree("cap", &["a/", "b/", "c", "d", "e"]);
+        let result = list_directory(
+            &root,
+            &ReadOptions {
+                max_entries: Some(2),
+                ..ReadOptions::default()
+            },
+            &PathFilter::default(),
+        )
+        .unwrap();
+        let empty = list_directory(
+            &root.join("a"),
+            &ReadOptions::default(),
+            &PathFilter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(entry_names(&result), ["a/", "b/"]);
+        assert!(result.ends_with(
+            "</directory>\nOnly the first 2 entries were listed, 0 directories and 3 files were omitted. List subdirectories separately or increase `max_entries` in `read_options` to see more.\n2 directories at depth 1 were not expanded, increase `depth` in `read_options` to list their contents.\n"
+        ));
+        assert_eq!(
+            empty,
+            format!(
+                "<directory path=\"{}\">\n</directory>\n",
+                root.join("a").display()
+            )
+        );
+
+        for index in 0..=MAX_OMITTED_ENTRIES {
+            std::fs::write(root.join(format!("file-{index}")), "").unwrap();
+        }
+        let result = list_directory(
+            &root,
+            &ReadOptions {
+                max_entries: Some(2),
+                ..ReadOptions::default()
+            },
+            &PathFilter::default(),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(result.contains(&format!(
+            "Only the first 2 entries were listed, more than {MAX_OMITTED_ENTRIES} entries were omitted."
+        )));
+    }
+
+    #[test]
+    fn leaves_out_denied_entries() {
+        let root = create_tree("denied", &[".env", ".aws/", ".aws/credentials", "main.rs"]);
+        let policy = PathFilter::new(&[productivity_config::ToolRule {
+            denied_paths: vec!["**/.env".to_string(), "**/.aws".to_string()],
+            ..productivity_config::ToolRule::default()
+        }])
+        .unwrap();
+        let result = list_directory(
+            &root,
+            &ReadOptions {
+                depth: Some(2),
+                ..ReadOptions::default()
+            },
+            &policy,
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(entry_names(&result), ["main.rs"]);
+        assert!(result.ends_with(
+            "</directory>\n2 entries were not listed since the tool policy denies reading them.\n"
+        ));
+    }
+
+    #[test]
+    fn parses_editors() {
+        assert_eq!(parse_editor("vim"), Some(vec!["vim".to_string()]));
+        assert_eq!(
+            parse_editor(" code --wait "),
+            Some(vec!["code".to_string(), "--wait".to_string()])
+        );
+        assert_eq!(parse_editor(""), None);
+        assert_eq!(parse_editor("  "), None);
+        assert_eq!(parse_editor("\"\""), None);
+    }
+
+    #[test]
+    fn edits_content() {
+        // The editor replaces the file's content and checks its permissions
+        let editor = parse_editor(
+            r#"sh -c 'test "$(stat -c %a "$0" 2>/dev/null || stat -f %Lp "$0")" = 600 && printf edited > "$0"'"#,
+        )
+        .unwrap();
+        let edited = edit_content(
+            &SystemCommandRunner,
+            &editor,
+            Path::new("/tmp/main.rs"),
+            "proposed",
+        )
+        .unwrap();
+        assert_eq!(edited, "edited");
+
+        let editor = parse_editor("false").unwrap();
+        let error = edit_content(
+            &SystemCommandRunner,
+            &editor,
+            Path::new("/tmp/main.rs"),
+            "proposed",
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "false exited unsuccessfully");
+    }
+
+    #[test]
+    fn runs_the_editor_in_the_terminal() {
+        let runner = FakeCommandRunner::default();
+        let editor = parse_editor("code --wait").unwrap();
+        assert!(edit_content(&runner, &editor, Path::new("/src/main.rs"), "proposed").is_err());
+
+        let commands = runner.get_commands();
+        assert_eq!(commands[0].program, "code");
+        assert_eq!(commands[0].arguments[0], "--wait");
+        assert!(commands[0].arguments[1].ends_with("-main.rs"));
+        assert_eq!(commands[0].output_mode, OutputMode::Inherit);
+        assert_eq!(runner.get_suspended(), [true]);
+    }
+
+    #[test]
+    fn formats_diffs() {
+        let path = Path::new("/src/main.rs");
+        assert_eq!(
+            format_diff(path, "a\nb\nc\n", "a\nB\nc\nd\n", false),
+            "--- /src/main.rs\n+++ /src/main.rs\n@@ -1,3 +1,4 @@\n a\n-b\n+B\n c\n+d\n"
+        );
+        assert_eq!(format_diff(path, "a\n", "a\n", false), "");
+    }
+
+    #[test]
+    fn describes_writes() {
+        let path = Path::new("/src/main.rs");
+        assert_eq!(describe_write(path, "a\n", "a\n"), "Wrote to /src/main.rs");
+        assert_eq!(
+            describe_write(path, "a\nb\n", "a\nc\n"),
+            "The user modified the content before it was written to /src/main.rs, the changes they made to your content were:\n--- /src/main.rs\n+++ /src/main.rs\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+    }
+}
@@ -25,6 +25,18 @@ impl RustTool for HttpGetTool {
         "Send an HTTP GET request to the provided URL. This tool will return the response code and the body.".to_string()
     }
 
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        &[super::policy::RuleField::AllowedUrls]
+    }
+
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        super::policy::check_url(&input.url, rule)
+    }
+
     async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
         tracing::info!("Sending request to {}", &input.url);
 
@@ -1,9 +1,9 @@
 use core::str;
+use std::sync::Arc;
 
-use anyhow::Context;
-
+use super::command_runner::{CommandRunner, CommandSpec, SystemCommandRunner};
 use super::RustTool;
-use crate::tools::ToolPrerequisites;
+use crate::tools::{PrerequisiteFailure, ToolPrerequisites};
 
 /// The kubernetes context.
 #[derive(serde::Deserialize, schemars::JsonSchema)]
@@ -19,8 +19,61 @@ pub struct ArgocdStatusInput {
     kubernetes_context: KubernetesContext,
 }
 
+/// Check that a Kubernetes context exists in the kubeconfig.
+fn check_kubernetes_context(
+    runner: &dyn CommandRunner,
+    context: Option<String>,
+) -> Result<(), PrerequisiteFailure> {
+    // Missing input is reported when the tool is run
+    let Some(context) = context else {
+        return Ok(());
+    };
+    let Ok(output) =
+        runner.run(&CommandSpec::new("kubectl").args(["config", "get-contexts", "--output=name"]))
+    else {
+        return Ok(());
+    };
+    if !output.success() {
+        return Err(PrerequisiteFailure::Failed {
+            message: format!(
+                "The Kubernetes contexts could not be listed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            hint: Some("Check the kubeconfig is valid".to_string()),
+        });
+    }
+
+    let contexts: Vec<&str> = str::from_utf8(&output.stdout)
+        .unwrap_or_default()
+        .lines()
+        .collect();
+    if contexts.contains(&context.as_str()) {
+        Ok(())
+    } else {
+        Err(PrerequisiteFailure::Failed {
+            message: format!("The Kubernetes context {context} does not exist"),
+            hint: Some(if contexts.is_empty() {
+                "There are no Kubernetes contexts, configure one with `kubectl config set-context`"
+                    .to_string()
+            } else {
+                format!("Use one of the contexts: {}", contexts.join(", "))
+            }),
+        })
+    }
+}
+
 /// A tool that collects status information from `ArgoCD`
-pub struct ArgocdStatusTool;
+pub struct ArgocdStatusTool {
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl ArgocdStatusTool {
+    pub fn new() -> Self {
+        Self {
+            runner: Arc::new(SystemCommandRunner),
+        }
+    }
+}
 
 impl RustTool for ArgocdStatusTool {
     type Input = ArgocdStatusInput;
@@ -35,28 +88,122 @@ impl RustTool for ArgocdStatusTool {
 
     fn get_prequisites(&self) -> ToolPrerequisites {
         ToolPrerequisites {
-            binaries: vec![String::from("argocd")],
+            binaries: vec![String::from("argocd"), String::from("kubectl")],
+            checks: vec![Arc::new({
+                let runner = self.runner.clone();
+                move |input| {
+                    let runner = runner.clone();
+                    let context = input
+                        .get("kubernetes_context_name")
+                        .and_then(serde_json::Value::as_str)
+                        .map(ToString::to_string);
+                    Box::pin(async move { check_kubernetes_context(runner.as_ref(), context) })
+                }
+            })],
+            ..Default::default()
         }
     }
 
-    async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
-        let kubectl_output = std::process::Command::new("argocd")
-            .arg("repo")
-            .arg("list")
-            .arg("--output=json")
-            .arg(format!(
-                "--kube-context={}",
-                input.kubernetes_context.kubernetes_context_name
-            ))
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .output()
-            .context("Failed to execute argocd repo list")?;
-
-        Ok(if kubectl_output.status.success() {
+    async fn run(self: Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
+        let kubectl_output = self.runner.run(
+            &CommandSpec::new("argocd")
+                .args(["repo", "list", "--output=json"])
+                .arg(format!(
+                    "--kube-context={}",
+                    input.kubernetes_context.kubernetes_context_name
+                )),
+        )?;
+
+        Ok(if kubectl_output.success() {
             str::from_utf8(&kubectl_output.stdout)?.to_string()
         } else {
             str::from_utf8(&kubectl_output.stderr)?.to_string()
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::{block_on, FakeCommandRunner};
+
+    const ARGOCD: &[&str] = &[
+        "argocd",
+        "repo",
+        "list",
+        "--output=json",
+        "--kube-context=prod",
+    ];
+    const GET_CONTEXTS: &[&str] = &["kubectl", "config", "get-contexts", "--output=name"];
+
+    /// Get the status of the `prod` context.
+    fn get_status(runner: FakeCommandRunner) -> String {
+        let tool = Arc::new(ArgocdStatusTool {
+            runner: Arc::new(runner),
+        });
+        let input = ArgocdStatusInput {
+            kubernetes_context: KubernetesContext {
+                kubernetes_context_name: "prod".to_string(),
+            },
+        };
+        block_on(RustTool::run(tool, input)).unwrap()
+    }
+
+    /// Check the prerequisites of an invocation for a context.
+    fn check_context(
+        runner: FakeCommandRunner,
+        context: &str,
+    ) -> Result<(), Vec<PrerequisiteFailure>> {
+        let tool = ArgocdStatusTool {
+            runner: Arc::new(runner),
+        };
+        block_on(
+            RustTool::get_prequisites(&tool)
+                .check_input(&serde_json::json!({ "kubernetes_context_name": context })),
+        )
+    }
+
+    #[test]
+    fn status() {
+        let runner = FakeCommandRunner::default().with_output(ARGOCD, 0, "[]", "");
+        assert_eq!(get_status(runner), "[]");
+
+        let runner = FakeCommandRunner::default().with_output(ARGOCD, 20, "", "unauthorized");
+        assert_eq!(get_status(runner), "unauthorized");
+    }
+
+    #[test]
+    fn context_exists() {
+        let runner =
+            FakeCommandRunner::default().with_output(GET_CONTEXTS, 0, "prod\nstaging\n", "");
+        assert_eq!(check_context(runner, "prod"), Ok(()));
+    }
+
+    #[test]
+    fn context_does_not_exist() {
+        let runner =
+            FakeCommandRunner::default().with_output(GET_CONTEXTS, 0, "prod\nstaging\n", "");
+        assert_eq!(
+            check_context(runner, "dev"),
+            Err(vec![PrerequisiteFailure::Failed {
+                message: "The Kubernetes context dev does not exist".to_string(),
+                hint: Some("Use one of the contexts: prod, staging".to_string()),
+            }])
+        );
+    }
+
+    #[test]
+    fn contexts_cannot_be_listed() {
+        let runner = FakeCommandRunner::default().with_output(
+            GET_CONTEXTS,
+            1,
+            "",
+            "error: invalid kubeconfig\n",
+        );
+        let failures = check_context(runner, "prod").unwrap_err();
+        assert_eq!(
+            failures[0].to_string(),
+            "The Kubernetes contexts could not be listed: error: invalid kubeconfig"
+        );
+    }
+}
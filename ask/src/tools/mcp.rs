@@ -0,0 +1,557 @@
+//! Tools provided by Model Context Protocol servers.
+//!
+//! Each server in the config is launched with its stdin and stdout used for newline delimited JSON-RPC messages, see
+//! <https://modelcontextprotocol.io/specification/2024-11-05/basic/transports>.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{future::Future, pin::Pin};
+
+use anyhow::Context;
+
+use super::{Tool, ToolDefinition};
+
+/// The version of the protocol `ask` implements.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// How long a server has to start and list its tools.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a tool call can take.
+const CALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long a server has to exit after its stdin is closed before it's killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The number of lines of a server's stderr to keep for diagnostics.
+const MAX_STDERR_LINES: usize = 50;
+
+/// The connection to a running server.
+struct Connection {
+    /// The server's stdin, `None` once it has been closed to shut the server down.
+    stdin: Option<std::process::ChildStdin>,
+    /// The lines the server writes to stdout.
+    stdout: mpsc::Receiver<String>,
+}
+
+/// A running MCP server.
+pub struct McpServer {
+    name: String,
+    child: Mutex<std::process::Child>,
+    connection: Mutex<Connection>,
+    next_id: AtomicU64,
+    /// The most recent lines the server wrote to stderr.
+    stderr: Arc<Mutex<VecDeque<String>>>,
+    /// The thread reading the server's stderr.
+    stderr_thread: std::thread::JoinHandle<()>,
+}
+
+/// Forward the lines of a reader to a channel until it closes.
+fn forward_lines(reader: impl std::io::Read + Send + 'static, sender: mpsc::Sender<String>) {
+    std::thread::spawn(move || {
+        for line in std::io::BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+impl McpServer {
+    /// Launch a server and perform the initialize handshake.
+    pub fn start(config: &productivity_config::McpServerConfig) -> anyhow::Result<Self> {
+        let mut child = std::process::Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Running {}", config.command))?;
+
+        let (stdout_sender, stdout_receiver) = mpsc::channel();
+        forward_lines(
+            child.stdout.take().context("Getting the server's stdout")?,
+            stdout_sender,
+        );
+
+        let stderr = Arc::new(Mutex::new(VecDeque::with_capacity(MAX_STDERR_LINES)));
+        let server_stderr = child.stderr.take().context("Getting the server's stderr")?;
+        let server_name = config.name.clone();
+        let stderr_lines = stderr.clone();
+        let stderr_thread = std::thread::spawn(move || {
+            for line in std::io::BufReader::new(server_stderr).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                tracing::debug!("MCP server {server_name}: {line}");
+                let mut lines = stderr_lines.lock().unwrap();
+                if lines.len() == MAX_STDERR_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+        });
+
+        let server = Self {
+            name: config.name.clone(),
+            connection: Mutex::new(Connection {
+                stdin: child.stdin.take(),
+                stdout: stdout_receiver,
+            }),
+            child: Mutex::new(child),
+            next_id: AtomicU64::new(1),
+            stderr,
+            stderr_thread,
+        };
+
+        server
+            .request(
+                "initialize",
+                serde_json::json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "ask",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+                STARTUP_TIMEOUT,
+            )
+            .context("Initializing")?
+            .map_err(|e| anyhow::anyhow!("The server refused to initialize: {e}"))?;
+        server.notify("notifications/initialized")?;
+        Ok(server)
+    }
+
+    /// Get the most recent lines the server wrote to stderr.
+    pub fn get_stderr(&self) -> String {
+        let lines = self.stderr.lock().unwrap();
+        lines.iter().cloned().collect::<Vec<_>>().join("\n")
+    }
+
+    /// Give the stderr thread a moment to read the last words of a server which exited.
+    fn wait_for_stderr(&self) {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !self.stderr_thread.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Add the server's recent stderr to an error.
+    fn with_stderr(&self, error: anyhow::Error) -> anyhow::Error {
+        let stderr = self.get_stderr();
+        if stderr.is_empty() {
+            error
+        } else {
+            error.context(format!(
+                "The MCP server {} recently wrote to stderr:\n{stderr}",
+                self.name
+            ))
+        }
+    }
+
+    /// Write a message to the server.
+    fn send(connection: &mut Connection, message: &serde_json::Value) -> anyhow::Result<()> {
+        let stdin = connection
+            .stdin
+            .as_mut()
+            .context("The server has been shut down")?;
+        let mut line = serde_json::to_string(message).context("Serializing the message")?;
+        line.push('\n');
+        stdin
+            .write_all(line.as_bytes())
+            .and_then(|()| stdin.flush())
+            .context("Writing to the server")
+    }
+
+    /// Send a notification, which has no response.
+    fn notify(&self, method: &str) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        Self::send(
+            &mut connection,
+            &serde_json::json!({ "jsonrpc": "2.0", "method": method }),
+        )
+        .map_err(|e| self.with_stderr(e))
+    }
+
+    /// Send a request and wait for its response.
+    ///
+    /// A JSON-RPC error response is returned as `Ok(Err(message))` since it's a problem with the request rather than the server.
+    fn request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> anyhow::Result<Result<serde_json::Value, String>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut connection = self.connection.lock().unwrap();
+        Self::send(
+            &mut connection,
+            &serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }),
+        )
+        .map_err(|e| {
+            // Writing fails when the server has already exited
+            self.wait_for_stderr();
+            self.with_stderr(e)
+        })?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = match connection
+                .stdout
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(line) => line,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(self.with_stderr(anyhow::anyhow!(
+                        "The MCP server {} didn't respond to {method} within {timeout:?}",
+                        self.name
+                    )));
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    self.wait_for_stderr();
+                    return Err(
+                        self.with_stderr(anyhow::anyhow!("The MCP server {} exited", self.name))
+                    );
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!(
+                        "Ignoring invalid output from the MCP server {}: {e}",
+                        self.name
+                    );
+                    continue;
+                }
+            };
+
+            // Requests from the server, `ask` only supports pings
+            if let (Some(request_method), Some(request_id)) = (
+                message.get("method").and_then(|m| m.as_str()),
+                message.get("id"),
+            ) {
+                let response = if request_method == "ping" {
+                    serde_json::json!({ "jsonrpc": "2.0", "id": request_id, "result": {} })
+                } else {
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": request_id,
+                        "error": { "code": -32601, "message": format!("{request_method} is not supported") },
+                    })
+                };
+                Self::send(&mut connection, &response).map_err(|e| self.with_stderr(e))?;
+                continue;
+            }
+
+            // Skip notifications and responses to other requests
+            if message.get("id").and_then(serde_json::Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                let error_message = error
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .map_or_else(|| error.to_string(), ToString::to_string);
+                return Ok(Err(error_message));
+            }
+            return Ok(Ok(message.get("result").cloned().unwrap_or_default()));
+        }
+    }
+
+    /// List the server's tools, following pagination.
+    fn list_tools(&self) -> anyhow::Result<Vec<serde_json::Value>> {
+        let mut tools = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => serde_json::json!({ "cursor": cursor }),
+                None => serde_json::json!({}),
+            };
+            let result = self
+                .request("tools/list", params, STARTUP_TIMEOUT)?
+                .map_err(|e| anyhow::anyhow!("Listing tools: {e}"))?;
+            if let Some(page) = result.get("tools").and_then(|tools| tools.as_array()) {
+                tools.extend(page.iter().cloned());
+            }
+            cursor = result
+                .get("nextCursor")
+                .and_then(|cursor| cursor.as_str())
+                .map(ToString::to_string);
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    /// Call one of the server's tools, returning the text to send to the LLM.
+    fn call_tool(&self, name: &str, arguments: serde_json::Value) -> anyhow::Result<String> {
+        let result = match self.request(
+            "tools/call",
+            serde_json::json!({ "name": name, "arguments": arguments }),
+            CALL_TIMEOUT,
+        )? {
+            Ok(result) => result,
+            Err(message) => return Ok(format!("The MCP server returned an error: {message}")),
+        };
+
+        let mut parts = vec![];
+        for content in result
+            .get("content")
+            .and_then(|content| content.as_array())
+            .into_iter()
+            .flatten()
+        {
+            match content.get("type").and_then(|t| t.as_str()) {
+                Some("text") => parts.push(
+                    content
+                        .get("text")
+                        .and_then(|text| text.as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                ),
+                Some("resource") => {
+                    let resource = &content["resource"];
+                    match resource.get("text").and_then(|text| text.as_str()) {
+                        Some(text) => parts.push(text.to_string()),
+                        None => parts.push(format!(
+                            "[Binary resource {} omitted]",
+                            resource["uri"].as_str().unwrap_or("without a URI")
+                        )),
+                    }
+                }
+                Some(other) => parts.push(format!("[{other} content omitted]")),
+                None => {}
+            }
+        }
+
+        let text = parts.join("\n");
+        if result.get("isError").and_then(serde_json::Value::as_bool) == Some(true) {
+            Ok(format!("The tool failed: {text}"))
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+impl Drop for McpServer {
+    /// Shut the server down by closing its stdin, killing it if it doesn't exit.
+    fn drop(&mut self) {
+        if let Ok(connection) = self.connection.get_mut() {
+            connection.stdin.take();
+        }
+        let Ok(child) = self.child.get_mut() else {
+            return;
+        };
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        tracing::warn!(
+            "Killing the MCP server {} which didn't shut down",
+            self.name
+        );
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// A tool provided by an MCP server.
+pub struct McpTool {
+    server: Arc<McpServer>,
+    /// The name of the tool on the server.
+    server_tool_name: String,
+    definition: ToolDefinition,
+}
+
+impl Tool for McpTool {
+    fn get_definition(&self) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    fn run(
+        self: Arc<Self>,
+        input: serde_json::Value,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>>>> {
+        Box::pin(async move { self.server.call_tool(&self.server_tool_name, input) })
+    }
+}
+
+/// Start a server and create its tools.
+fn start_server(
+    config: &productivity_config::McpServerConfig,
+) -> anyhow::Result<Vec<Arc<dyn Tool>>> {
+    let server = Arc::new(McpServer::start(config)?);
+    let valid_name = regex::Regex::new("^[a-zA-Z0-9_-]{1,64}$")?;
+    let mut tools: Vec<Arc<dyn Tool>> = vec![];
+    for tool in server.list_tools()? {
+        let Some(server_tool_name) = tool.get("name").and_then(|name| name.as_str()) else {
+            continue;
+        };
+        // Prefix the names of tools so tools with common names from different servers don't collide
+        let name = format!("{}_{server_tool_name}", config.name);
+        if !valid_name.is_match(&name) {
+            tracing::warn!("Skipping the MCP tool {name} since its name is invalid");
+            continue;
+        }
+
+        let description = tool
+            .get("description")
+            .and_then(|description| description.as_str())
+            .unwrap_or_default();
+        tools.push(Arc::new(McpTool {
+            server: server.clone(),
+            server_tool_name: server_tool_name.to_string(),
+            definition: ToolDefinition {
+                name,
+                description: format!(
+                    "{description}\nThis tool is provided by the {} MCP server.",
+                    config.name
+                ),
+                input_schema: tool
+                    .get("inputSchema")
+                    .cloned()
+                    .unwrap_or_else(|| serde_json::json!({ "type": "object", "properties": {} })),
+            },
+        }));
+    }
+    Ok(tools)
+}
+
+/// Check whether a tool name could belong to an MCP server, the names of its tools are prefixed with its name.
+pub fn is_server_tool(config: &productivity_config::McpServerConfig, name: &str) -> bool {
+    name.strip_prefix(&config.name)
+        .is_some_and(|rest| rest.starts_with('_'))
+}
+
+/// Start the MCP servers in the config and create their tools, skipping servers which fail to start.
+pub fn get_mcp_tools<'a>(
+    configs: impl IntoIterator<Item = &'a productivity_config::McpServerConfig>,
+) -> Vec<Arc<dyn Tool>> {
+    let mut tools = vec![];
+    for config in configs {
+        match start_server(config) {
+            Ok(server_tools) => tools.extend(server_tools),
+            Err(e) => tracing::warn!("Skipping the MCP server {}: {e:#}", config.name),
+        }
+    }
+    tools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tiny MCP server with an `echo` tool and a `fail` tool.
+    const TEST_SERVER: &str = r#"
+echo "test server started" >&2
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"test","version":"1"}}}\n' "$id";;
+    *'"method":"tools/list"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[{"name":"echo","description":"Echo text.","inputSchema":{"type":"object","properties":{"text":{"type":"string"}},"required":["text"]}},{"name":"fail","inputSchema":{"type":"object"}}]}}\n' "$id";;
+    *'"name":"echo"'*)
+      text=$(printf '%s' "$line" | sed -n 's/.*"text":"\([^"]*\)".*/\1/p')
+      printf '{"jsonrpc":"2.0","method":"notifications/message","params":{}}\n'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"echo: %s"}]}}\n' "$id" "$text";;
+    *'"name":"fail"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"it broke"}],"isError":true}}\n' "$id";;
+    *'"id":'*)
+      printf '{"jsonrpc":"2.0","id":%s,"error":{"code":-32601,"message":"unknown method"}}\n' "$id";;
+  esac
+done
+"#;
+
+    fn test_config(script: &str) -> productivity_config::McpServerConfig {
+        productivity_config::McpServerConfig {
+            name: "test".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: std::collections::HashMap::new(),
+        }
+    }
+
+    fn run_tool(tool: &Arc<dyn Tool>, input: serde_json::Value) -> String {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(tool.clone().run(input))
+            .unwrap()
+    }
+
+    #[test]
+    fn lists_and_calls_tools() {
+        let tools = start_server(&test_config(TEST_SERVER)).unwrap();
+        let definitions: Vec<_> = tools.iter().map(|tool| tool.get_definition()).collect();
+        assert_eq!(definitions.len(), 2);
+        assert_eq!(definitions[0].name, "test_echo");
+        assert!(definitions[0].description.starts_with("Echo text."));
+        assert_eq!(
+            definitions[0].input_schema["properties"]["text"]["type"],
+            "string"
+        );
+        assert_eq!(definitions[1].name, "test_fail");
+
+        assert_eq!(
+            run_tool(&tools[0], serde_json::json!({ "text": "hello" })),
+            "echo: hello"
+        );
+        assert_eq!(
+            run_tool(&tools[1], serde_json::json!({})),
+            "The tool failed: it broke"
+        );
+    }
+
+    #[test]
+    fn captures_stderr() {
+        let server = McpServer::start(&test_config(TEST_SERVER)).unwrap();
+        // The stderr thread runs concurrently so give it a moment
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while server.get_stderr().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.get_stderr(), "test server started");
+    }
+
+    #[test]
+    fn error_responses() {
+        let server = McpServer::start(&test_config(TEST_SERVER)).unwrap();
+        let response = server
+            .request("resources/list", serde_json::json!({}), STARTUP_TIMEOUT)
+            .unwrap();
+        assert_eq!(response, Err("unknown method".to_string()));
+    }
+
+    #[test]
+    fn failed_start() {
+        let error = McpServer::start(&test_config("echo 'bad credentials' >&2; exit 1"))
+            .err()
+            .unwrap();
+        assert!(format!("{error:#}").contains("bad credentials"));
+    }
+
+    #[test]
+    fn shuts_down_servers_which_ignore_stdin() {
+        // Keep running after stdin is closed
+        let script = TEST_SERVER.replace("done\n", "done\nexec sleep 60\n");
+        let server = McpServer::start(&test_config(&script)).unwrap();
+        let started = Instant::now();
+        drop(server);
+        assert!(started.elapsed() < SHUTDOWN_TIMEOUT + Duration::from_secs(5));
+    }
+}
@@ -3,19 +3,39 @@
 use std::{future::Future, path::PathBuf, pin::Pin, sync::Arc};
 
 use anyhow::Context;
+use serde::Deserialize;
 
 pub mod rust_tools;
 
+pub use policy::{check_enforced, RuleField};
+pub use prerequisites::{
+    describe_failures, InputPath, MinimumVersion, PrerequisiteFailure, ToolPrerequisites,
+};
+pub use registry::ToolRegistry;
+
 mod binary_tool;
 mod cloud_context;
+mod command_runner;
+mod custom_tool;
+mod file_access;
 mod filesystem;
 mod http_request;
 mod kubernetes;
+mod mcp;
 mod open;
 mod package_manager;
+mod plugin;
+mod policy;
+mod prerequisites;
+mod registry;
+mod search;
 mod software_versions;
 mod terraform;
 
+/// The truncation hint for tools which don't have a better way to get the rest of a result.
+const DEFAULT_TRUNCATION_HINT: &str =
+    "Run the tool again with input that produces a smaller result to see the omitted part";
+
 /// A tool definition.
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 pub struct ToolDefinition {
@@ -29,29 +49,49 @@ pub struct ToolDefinition {
     pub input_schema: serde_json::Value,
 }
 
-/// The prerequisites required to run a tool.
-#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
-pub struct ToolPrerequisites {
-    /// The binaries that must be available on the system for the tool to run.
-    pub binaries: Vec<String>,
+/// Get every available tool: the Rust tools followed by the tools declared in the config, the plugins, and the tools of
+/// the MCP servers.
+///
+/// Tools with the same name as an earlier tool are skipped.
+pub fn get_all_tools(config: &productivity_config::Config) -> ToolRegistry {
+    get_selected_tools(config, None)
 }
 
-impl ToolPrerequisites {
-    /// Check if the prerequisites are satisfied.
-    pub fn is_satisfied(&self) -> Result<(), String> {
-        let mut errors = vec![];
-        for binary in &self.binaries {
-            if which::which(binary).is_err() {
-                errors.push(format!("{} is not present on the system", &binary));
-            }
-        }
+/// Get the available tools like `get_all_tools`, but with a selection of tool names only run the plugins and start the
+/// MCP servers which could provide a selected tool.
+pub fn get_selected_tools(
+    config: &productivity_config::Config,
+    selected: Option<&[String]>,
+) -> ToolRegistry {
+    ToolRegistry::from_sources(
+        rust_tools::get_rust_tools(),
+        custom_tool::get_custom_tools(&config.custom_tools),
+        plugin::get_plugin_tools(selected),
+        mcp::get_mcp_tools(config.mcp_servers.iter().filter(|server| {
+            selected.is_none_or(|selected| {
+                selected
+                    .iter()
+                    .any(|name| mcp::is_server_tool(server, name))
+            })
+        })),
+    )
+}
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors.join("\n"))
-        }
-    }
+/// Get the names of the tools which could be available without running plugins or starting MCP servers, the tools of an
+/// MCP server are only known once it starts so they're named `<server>_*`.
+pub fn get_tool_names(config: &productivity_config::Config) -> Vec<String> {
+    rust_tools::get_rust_tools()
+        .iter()
+        .map(|tool| tool.get_definition().name)
+        .chain(config.custom_tools.iter().map(|tool| tool.name.clone()))
+        .chain(plugin::get_plugin_names())
+        .chain(
+            config
+                .mcp_servers
+                .iter()
+                .map(|server| format!("{}_*", server.name)),
+        )
+        .collect()
 }
 
 /// A tool that the LLMs can run.
@@ -63,7 +103,26 @@ pub trait Tool {
 
     /// Get the prerequisites required to run the tool.
     fn get_prequisites(&self) -> ToolPrerequisites {
-        ToolPrerequisites { binaries: vec![] }
+        ToolPrerequisites::default()
+    }
+
+    /// Explain to the LLM how to get the rest of a result which was truncated because it was too large.
+    fn get_truncation_hint(&self) -> String {
+        DEFAULT_TRUNCATION_HINT.to_string()
+    }
+
+    /// Get the fields of a tool policy rule which `check_policy` enforces.
+    fn get_enforced_rule_fields(&self) -> &'static [RuleField] {
+        &[]
+    }
+
+    /// Check that an invocation is allowed by the rule for this tool in the user's tool policy.
+    fn check_policy(
+        &self,
+        _input: &serde_json::Value,
+        _rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        Ok(())
     }
 
     /// Run the tool.
@@ -86,7 +145,26 @@ pub trait RustTool {
 
     /// Get the prerequisites required to run the tool.
     fn get_prequisites(&self) -> ToolPrerequisites {
-        ToolPrerequisites { binaries: vec![] }
+        ToolPrerequisites::default()
+    }
+
+    /// Explain to the LLM how to get the rest of a result which was truncated because it was too large.
+    fn get_truncation_hint(&self) -> String {
+        DEFAULT_TRUNCATION_HINT.to_string()
+    }
+
+    /// Get the fields of a tool policy rule which `check_policy` enforces.
+    fn get_enforced_rule_fields(&self) -> &'static [RuleField] {
+        &[]
+    }
+
+    /// Check that an invocation is allowed by the rule for this tool in the user's tool policy.
+    fn check_policy(
+        &self,
+        _input: &Self::Input,
+        _rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        Ok(())
     }
 
     /// Run the tool.
@@ -111,6 +189,26 @@ where
         RustTool::get_prequisites(self)
     }
 
+    fn get_truncation_hint(&self) -> String {
+        RustTool::get_truncation_hint(self)
+    }
+
+    fn get_enforced_rule_fields(&self) -> &'static [RuleField] {
+        RustTool::get_enforced_rule_fields(self)
+    }
+
+    fn check_policy(
+        &self,
+        input: &serde_json::Value,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        // Invalid input is reported when the tool is run
+        match <Self as RustTool>::Input::deserialize(input) {
+            Ok(input) => RustTool::check_policy(self, &input, rule),
+            Err(_) => Ok(()),
+        }
+    }
+
     fn run(
         self: Arc<Self>,
         input: serde_json::Value,
@@ -132,15 +230,14 @@ where
 }
 
 /// Get an appropriate cache directory for a tool.
-fn get_cache_dir_for_tool(tool: &dyn Tool) -> anyhow::Result<PathBuf> {
+fn get_cache_dir(tool_name: &str) -> anyhow::Result<PathBuf> {
     let config = productivity_config::Config::get_or_default()?;
-    let tool_name = tool.get_definition().name;
-    let cache_dir = config.cache_location.join(&tool_name);
+    let cache_dir = config.cache_location.join(tool_name);
     std::fs::create_dir_all(&cache_dir).with_context(|| {
         format!(
             "Creating cache directory {} for {}",
             cache_dir.display(),
-            &tool_name
+            tool_name
         )
     })?;
     Ok(cache_dir)
@@ -26,6 +26,33 @@ impl RustTool for OpenTool {
         "Open a file or URL on the user's computer.".to_string()
     }
 
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        &[
+            super::policy::RuleField::AllowedPaths,
+            super::policy::RuleField::DeniedPaths,
+            super::policy::RuleField::AllowedUrls,
+        ]
+    }
+
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        match reqwest::Url::parse(&input.path) {
+            // `file` URLs are paths, so they can't be used to open files outside the allowed paths
+            Ok(url) if url.scheme() == "file" => {
+                let path = url
+                    .to_file_path()
+                    .map_err(|()| format!("{} is not a valid file URL", input.path))?;
+                super::policy::check_paths([path.to_string_lossy().as_ref()], rule)
+            }
+            // Windows paths such as `C:\file` parse as URLs with a single letter scheme
+            Ok(url) if url.scheme().len() > 1 => super::policy::check_url(&input.path, rule),
+            _ => super::policy::check_paths([input.path.as_str()], rule),
+        }
+    }
+
     async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
         let open_result = if input.is_source_code {
             if let Ok(editor) = std::env::var("EDITOR") {
@@ -53,3 +80,34 @@ impl RustTool for OpenTool {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check opening a path against a rule.
+    fn check(path: &str, rule: &productivity_config::ToolRule) -> Result<(), String> {
+        OpenTool.check_policy(
+            &OpenToolInput {
+                path: path.to_string(),
+                is_source_code: false,
+            },
+            rule,
+        )
+    }
+
+    #[test]
+    fn checks_paths_and_urls() {
+        let rule = productivity_config::ToolRule {
+            allowed_paths: vec!["/src/**".to_string()],
+            allowed_urls: vec!["https://docs.rs/**".to_string()],
+            ..productivity_config::ToolRule::default()
+        };
+        assert_eq!(check("/src/main.rs", &rule), Ok(()));
+        assert!(check("/etc/passwd", &rule).is_err());
+        assert!(check("file:///etc/passwd", &rule).is_err());
+        assert_eq!(check("file:///src/main.rs", &rule), Ok(()));
+        assert_eq!(check("https://docs.rs/regex", &rule), Ok(()));
+        assert!(check("https://example.com", &rule).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! Tool to install software.
 
-use anyhow::Context;
+use std::sync::Arc;
 
+use super::command_runner::{CommandRunner, CommandSpec, OutputMode, SystemCommandRunner};
 use super::RustTool;
 
 enum PackageManager {
@@ -22,30 +23,41 @@ impl PackageManager {
         }
     }
 
+    /// Get the command to install a list of packages.
+    fn get_install_command(&self, packages: &[String]) -> String {
+        let package_manager = self.get_name();
+        match self {
+            PackageManager::Brew => format!("brew install {}", packages.join(" ")),
+            PackageManager::Apt | PackageManager::Dnf => {
+                format!("sudo {package_manager} install {}", packages.join(" "))
+            }
+            PackageManager::Pacman => format!("sudo pacman -S {}", packages.join(" ")),
+        }
+    }
+
     /// Install a list of packages.
-    fn install(&self, packages: Vec<String>) -> anyhow::Result<()> {
+    fn install(&self, runner: &dyn CommandRunner, packages: Vec<String>) -> anyhow::Result<()> {
         let package_manager = self.get_name();
-        let mut command = if let Self::Brew = self {
-            std::process::Command::new(package_manager)
+        let command = if let Self::Brew = self {
+            CommandSpec::new(package_manager)
         } else {
-            let mut command = std::process::Command::new("sudo");
-            command.arg(package_manager);
-            command
+            CommandSpec::new("sudo").arg(package_manager)
         };
-        match self {
+        let command = match self {
             PackageManager::Apt | PackageManager::Brew | PackageManager::Dnf => {
-                command.arg("install");
-            }
-            PackageManager::Pacman => {
-                command.arg("-S");
+                command.arg("install")
             }
-        }
-        command.args(packages);
-        let status = command
-            .status()
-            .context("Failed to run the package manager")?;
-        if !status.success() {
-            anyhow::bail!("The package manager failed with status code {}", status);
+            PackageManager::Pacman => command.arg("-S"),
+        };
+        // The package manager can prompt the user so it needs the terminal
+        let output = runner.run(&command.args(packages).output_mode(OutputMode::Inherit))?;
+        if !output.success() {
+            anyhow::bail!(
+                "The package manager failed with status code {}",
+                output
+                    .code
+                    .map_or_else(|| "none".to_string(), |code| code.to_string())
+            );
         }
         Ok(())
     }
@@ -69,10 +81,23 @@ impl PackageManager {
     }
 }
 
+/// Get the command to install the packages providing some binaries with the host's package manager.
+pub fn get_install_command(binaries: &[String]) -> String {
+    let packages: Vec<String> = binaries
+        .iter()
+        .map(|binary| match binary.as_str() {
+            "aws" => "awscli".to_string(),
+            binary => binary.to_string(),
+        })
+        .collect();
+    PackageManager::guess().get_install_command(&packages)
+}
+
 /// Tool to install software.
 pub struct PackageManagerTool {
     /// The underlying package manager binary.
     package_manager: PackageManager,
+    runner: Arc<dyn CommandRunner>,
 }
 
 /// Input to the package manager tool.
@@ -89,6 +114,7 @@ impl PackageManagerTool {
     pub fn new() -> Self {
         PackageManagerTool {
             package_manager: PackageManager::guess(),
+            runner: Arc::new(SystemCommandRunner),
         }
     }
 }
@@ -107,9 +133,55 @@ impl RustTool for PackageManagerTool {
         )
     }
 
-    async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
+    async fn run(self: Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
         let packages = input.packages_to_install.clone();
-        self.package_manager.install(packages)?;
+        self.package_manager
+            .install(self.runner.as_ref(), packages)?;
         Ok("Finished".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::FakeCommandRunner;
+
+    #[test]
+    fn install_commands() {
+        for (package_manager, command_line) in [
+            (PackageManager::Apt, &["sudo", "apt", "install", "jq"][..]),
+            (PackageManager::Brew, &["brew", "install", "jq"][..]),
+            (PackageManager::Dnf, &["sudo", "dnf", "install", "jq"][..]),
+            (PackageManager::Pacman, &["sudo", "pacman", "-S", "jq"][..]),
+        ] {
+            let runner = FakeCommandRunner::default().with_output(command_line, 0, "", "");
+            package_manager
+                .install(&runner, vec!["jq".to_string()])
+                .unwrap();
+            assert_eq!(runner.get_commands()[0].output_mode, OutputMode::Inherit);
+        }
+    }
+
+    #[test]
+    fn install_hides_the_spinner() {
+        let runner =
+            FakeCommandRunner::default().with_output(&["sudo", "apt", "install", "jq"], 0, "", "");
+        PackageManager::Apt
+            .install(&runner, vec!["jq".to_string()])
+            .unwrap();
+        assert_eq!(runner.get_suspended(), [true]);
+    }
+
+    #[test]
+    fn install_fails() {
+        let runner =
+            FakeCommandRunner::default().with_output(&["brew", "install", "jq"], 1, "", "");
+        let error = PackageManager::Brew
+            .install(&runner, vec!["jq".to_string()])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The package manager failed with status code 1"
+        );
+    }
+}
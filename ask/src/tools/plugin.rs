@@ -0,0 +1,483 @@
+//! External tool plugins.
+//!
+//! A plugin is an executable named `ask-tool-<name>` on `PATH` or in the `plugins` directory next to the config file.
+//! Running it with `--describe` prints its `ToolDefinition` as JSON, and running it without arguments reads the tool
+//! input as JSON from stdin and prints either `{"result": ...}` or `{"error": "..."}` as JSON to stdout.
+//! Both are sent to the LLM, as are the exit code and stderr of a plugin which exits unsuccessfully or doesn't finish
+//! in time.
+//!
+//! Definitions are cached until the plugin's executable changes so plugins aren't run every time `ask` starts.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use anyhow::Context;
+
+use super::command_runner::{CommandRunner, CommandSpec, SystemCommandRunner};
+use super::{Tool, ToolDefinition};
+
+/// The prefix of plugin executable names.
+const PLUGIN_PREFIX: &str = "ask-tool-";
+
+/// How long a plugin has to describe itself.
+const DESCRIBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long a plugin invocation can take.
+const CALL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// The output of a plugin invocation.
+#[derive(serde::Deserialize)]
+struct PluginOutput {
+    /// The result to send to the LLM, strings are sent as is and other values as JSON.
+    result: Option<serde_json::Value>,
+    /// Why the invocation failed.
+    error: Option<String>,
+}
+
+/// A tool implemented by an external executable.
+pub struct PluginTool {
+    path: PathBuf,
+    definition: ToolDefinition,
+    runner: Arc<dyn CommandRunner>,
+}
+
+/// A plugin's definition and the state of its executable when it described itself.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedDefinition {
+    modified: std::time::SystemTime,
+    size: u64,
+    definition: ToolDefinition,
+}
+
+/// The plugin definitions from previous runs, keyed by the path of the executable.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct DefinitionCache {
+    plugins: HashMap<String, CachedDefinition>,
+}
+
+impl DefinitionCache {
+    /// Load the cache, starting from an empty cache if it can't be read.
+    fn load(path: &Path) -> Self {
+        let Ok(file) = std::fs::File::open(path) else {
+            return Self::default();
+        };
+        serde_json::from_reader(file).unwrap_or_else(|e| {
+            tracing::warn!(
+                "Failed to read the plugin cache at {}: {}",
+                path.display(),
+                e
+            );
+            Self::default()
+        })
+    }
+
+    /// Write the cache back to disk.
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create the plugin cache at {}", path.display()))?;
+        serde_json::to_writer(file, self)
+            .with_context(|| format!("Failed to write the plugin cache at {}", path.display()))
+    }
+}
+
+/// Check whether a path is an executable file.
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// Get the name of the tool a plugin executable provides, the part of its name after the prefix.
+fn get_plugin_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()?.strip_prefix(PLUGIN_PREFIX)
+}
+
+/// Find the plugin executables, the plugins directory takes precedence over `PATH`.
+fn find_plugins() -> Vec<PathBuf> {
+    let mut directories = vec![];
+    match productivity_config::Config::get_config_file_path() {
+        Ok(config_file) => {
+            if let Some(config_directory) = config_file.parent() {
+                directories.push(config_directory.join("plugins"));
+            }
+        }
+        Err(e) => tracing::warn!("Could not find the plugins directory: {e}"),
+    }
+    if let Some(path) = std::env::var_os("PATH") {
+        directories.extend(std::env::split_paths(&path));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut plugins = vec![];
+    for directory in directories {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(PLUGIN_PREFIX))
+                    && is_executable(path)
+            })
+            .collect();
+        paths.sort();
+        for path in paths {
+            // Like `PATH` lookups, the first executable with a name wins
+            if seen.insert(path.file_name().map(ToOwned::to_owned)) {
+                plugins.push(path);
+            }
+        }
+    }
+    plugins
+}
+
+/// Ask a plugin for its tool definition.
+fn describe(runner: &dyn CommandRunner, path: &Path) -> anyhow::Result<ToolDefinition> {
+    let output = runner.run(
+        &CommandSpec::new(path.to_string_lossy())
+            .arg("--describe")
+            .timeout(DESCRIBE_TIMEOUT),
+    )?;
+    if !output.success() {
+        anyhow::bail!(
+            "Describing the plugin failed with {}:\n{}",
+            output.code.map_or_else(
+                || "a signal".to_string(),
+                |code| format!("exit code {code}")
+            ),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let definition: ToolDefinition =
+        serde_json::from_slice(&output.stdout).context("Parsing the plugin's tool definition")?;
+    if !regex::Regex::new("^[a-zA-Z0-9_-]{1,64}$")?.is_match(&definition.name) {
+        anyhow::bail!("The plugin's tool name {:?} is invalid", definition.name);
+    }
+    Ok(definition)
+}
+
+/// Create the tools for plugins, reusing the definitions cached at `cache_path` for executables which haven't changed.
+///
+/// With a selection of tool names only the plugins whose names are selected are described, the cached definitions of
+/// the others are kept.
+fn create_plugin_tools(
+    runner: &Arc<dyn CommandRunner>,
+    plugins: Vec<PathBuf>,
+    selected: Option<&[String]>,
+    cache_path: Option<&Path>,
+) -> Vec<Arc<dyn Tool>> {
+    let mut cache = cache_path.map(DefinitionCache::load).unwrap_or_default();
+    let mut fresh_cache = DefinitionCache::default();
+    let mut tools: Vec<Arc<dyn Tool>> = vec![];
+    for path in plugins {
+        let key = path.to_string_lossy().to_string();
+        let is_selected = selected.is_none_or(|selected| {
+            get_plugin_name(&path).is_some_and(|name| selected.iter().any(|tool| tool == name))
+        });
+        if !is_selected {
+            if let Some(cached) = cache.plugins.remove(&key) {
+                fresh_cache.plugins.insert(key, cached);
+            }
+            continue;
+        }
+
+        let metadata = match std::fs::metadata(&path).and_then(|metadata| {
+            metadata
+                .modified()
+                .map(|modified| (modified, metadata.len()))
+        }) {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                tracing::info!("Not caching the plugin {}: {e}", path.display());
+                None
+            }
+        };
+        let definition = match cache.plugins.remove(&key) {
+            Some(cached) if Some((cached.modified, cached.size)) == metadata => cached.definition,
+            _ => match describe(runner.as_ref(), &path) {
+                Ok(definition) => definition,
+                Err(e) => {
+                    tracing::warn!("Skipping the plugin {}: {e:#}", path.display());
+                    continue;
+                }
+            },
+        };
+        if let Some((modified, size)) = metadata {
+            fresh_cache.plugins.insert(
+                key,
+                CachedDefinition {
+                    modified,
+                    size,
+                    definition: definition.clone(),
+                },
+            );
+        }
+        tools.push(Arc::new(PluginTool {
+            path,
+            definition,
+            runner: runner.clone(),
+        }));
+    }
+
+    // Plugins which were removed are dropped from the cache
+    if let Some(Err(e)) = cache_path.map(|cache_path| fresh_cache.save(cache_path)) {
+        tracing::warn!("{e:#}");
+    }
+    tools
+}
+
+/// Get the names of the tools the plugins provide without running them.
+pub fn get_plugin_names() -> Vec<String> {
+    find_plugins()
+        .iter()
+        .filter_map(|path| get_plugin_name(path).map(ToString::to_string))
+        .collect()
+}
+
+/// Discover the plugins and create their tools, skipping any which can't describe themselves.
+///
+/// With a selection of tool names only the plugins whose names are selected are run.
+pub fn get_plugin_tools(selected: Option<&[String]>) -> Vec<Arc<dyn Tool>> {
+    let cache_path = super::get_cache_dir("plugins")
+        .map(|cache_dir| cache_dir.join("definitions.json"))
+        .inspect_err(|e| tracing::warn!("Could not get the plugin cache directory: {e:#}"))
+        .ok();
+    create_plugin_tools(
+        &(Arc::new(SystemCommandRunner) as Arc<dyn CommandRunner>),
+        find_plugins(),
+        selected,
+        cache_path.as_deref(),
+    )
+}
+
+impl PluginTool {
+    /// Run the plugin with the tool input on stdin.
+    fn invoke(&self, input: &serde_json::Value) -> anyhow::Result<String> {
+        tracing::info!("Running the plugin {}", self.path.display());
+        let input = serde_json::to_vec(input).context("Serializing the tool input")?;
+        // Plugins which fail are for the LLM to handle like errors they report
+        let output = match self.runner.run(
+            &CommandSpec::new(self.path.to_string_lossy())
+                .stdin(input)
+                .timeout(CALL_TIMEOUT),
+        ) {
+            Ok(output) => output,
+            Err(e) => return Ok(format!("The tool failed: {e:#}")),
+        };
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.success() {
+            return Ok(format!(
+                "The tool failed with {}:\n{stderr}",
+                output.code.map_or_else(
+                    || "a signal".to_string(),
+                    |code| format!("exit code {code}")
+                )
+            ));
+        }
+
+        let output: PluginOutput = serde_json::from_slice(&output.stdout).with_context(|| {
+            format!(
+                "Parsing the output of the plugin {}, its stderr was:\n{stderr}",
+                self.path.display()
+            )
+        })?;
+        match output {
+            // Errors reported by the plugin are for the LLM to handle
+            PluginOutput {
+                error: Some(error), ..
+            } => Ok(format!("The tool failed: {error}")),
+            PluginOutput {
+                result: Some(serde_json::Value::String(result)),
+                ..
+            } => Ok(result),
+            PluginOutput {
+                result: Some(result),
+                ..
+            } => Ok(result.to_string()),
+            PluginOutput { result: None, .. } => Ok(String::new()),
+        }
+    }
+}
+
+impl Tool for PluginTool {
+    fn get_definition(&self) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    fn run(
+        self: Arc<Self>,
+        input: serde_json::Value,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>>>> {
+        Box::pin(async move { self.invoke(&input) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::{block_on, FakeCommandRunner};
+
+    /// The definition printed by the test plugin.
+    const DEFINITION: &str = r#"{"name": "weather", "description": "Get the weather", "input_schema": {"type": "object"}}"#;
+
+    /// Create an executable in a new directory for a test, the fake runner doesn't run it.
+    fn create_plugin(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("ask-plugin-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("ask-tool-weather");
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        path
+    }
+
+    /// Create the tools for plugins with a fake runner.
+    fn create_tools(
+        runner: FakeCommandRunner,
+        plugins: &[&Path],
+        cache_path: Option<&Path>,
+    ) -> Vec<Arc<dyn Tool>> {
+        create_plugin_tools(
+            &(Arc::new(runner) as Arc<dyn CommandRunner>),
+            plugins.iter().map(|path| path.to_path_buf()).collect(),
+            None,
+            cache_path,
+        )
+    }
+
+    #[test]
+    fn describes_plugins() {
+        let runner = FakeCommandRunner::default()
+            .with_output(&["/good", "--describe"], 0, DEFINITION, "")
+            .with_output(&["/failing", "--describe"], 1, "", "broken")
+            .with_output(&["/garbage", "--describe"], 0, "usage: ...", "")
+            .with_output(
+                &["/bad-name", "--describe"],
+                0,
+                r#"{"name": "bad name", "description": "", "input_schema": {}}"#,
+                "",
+            );
+        assert!(format!(
+            "{:#}",
+            describe(&runner, Path::new("/failing")).unwrap_err()
+        )
+        .contains("exit code 1:\nbroken"));
+        assert!(describe(&runner, Path::new("/garbage")).is_err());
+        assert!(describe(&runner, Path::new("/bad-name")).is_err());
+        assert!(describe(&runner, Path::new("/missing")).is_err());
+        assert_eq!(
+            describe(&runner, Path::new("/good")).unwrap().description,
+            "Get the weather"
+        );
+        assert!(runner
+            .get_commands()
+            .iter()
+            .all(|command| command.timeout == Some(DESCRIBE_TIMEOUT)));
+
+        let tools = create_tools(runner, &[Path::new("/failing"), Path::new("/good")], None);
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].get_definition().name, "weather");
+    }
+
+    #[test]
+    fn caches_definitions_until_the_plugin_changes() {
+        let path = create_plugin("cache");
+        let cache_path = path.with_file_name("definitions.json");
+        let command_line = [path.to_str().unwrap(), "--describe"];
+
+        let tools = create_tools(
+            FakeCommandRunner::default().with_output(&command_line, 0, DEFINITION, ""),
+            &[&path],
+            Some(&cache_path),
+        );
+        assert_eq!(tools.len(), 1);
+        // The plugin isn't run again while it's unchanged
+        let tools = create_tools(FakeCommandRunner::default(), &[&path], Some(&cache_path));
+        assert_eq!(tools[0].get_definition().name, "weather");
+
+        // Plugins which aren't selected aren't run and stay cached
+        let runner = Arc::new(FakeCommandRunner::default());
+        let tools = create_plugin_tools(
+            &(runner.clone() as Arc<dyn CommandRunner>),
+            vec![path.clone()],
+            Some(&["other".to_string()]),
+            Some(&cache_path),
+        );
+        assert!(tools.is_empty());
+        assert!(runner.get_commands().is_empty());
+        let tools = create_tools(FakeCommandRunner::default(), &[&path], Some(&cache_path));
+        assert_eq!(tools[0].get_definition().name, "weather");
+
+        std::fs::write(&path, "#!/bin/sh\nexit 1\n").unwrap();
+        let tools = create_tools(FakeCommandRunner::default(), &[&path], Some(&cache_path));
+        assert!(tools.is_empty());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn runs_plugins() {
+        let run = |code: i32, stdout: &str| {
+            let runner = Arc::new(FakeCommandRunner::default().with_output(
+                &["/ask-tool-weather"],
+                code,
+                stdout,
+                "stderr",
+            ));
+            let tool = Arc::new(PluginTool {
+                path: PathBuf::from("/ask-tool-weather"),
+                definition: serde_json::from_str(DEFINITION).unwrap(),
+                runner: runner.clone(),
+            });
+            let result = block_on(tool.run(serde_json::json!({"city": "Paris"})));
+            assert_eq!(
+                runner.get_commands()[0].stdin.as_deref(),
+                Some(br#"{"city":"Paris"}"#.as_slice())
+            );
+            assert_eq!(runner.get_commands()[0].timeout, Some(CALL_TIMEOUT));
+            result.map_err(|e| format!("{e:#}"))
+        };
+
+        assert_eq!(run(0, r#"{"result": "Sunny"}"#).unwrap(), "Sunny");
+        assert_eq!(
+            run(0, r#"{"result": {"temperature": 20}}"#).unwrap(),
+            r#"{"temperature":20}"#
+        );
+        assert_eq!(
+            run(0, r#"{"error": "Unknown city"}"#).unwrap(),
+            "The tool failed: Unknown city"
+        );
+        assert_eq!(run(0, "{}").unwrap(), "");
+        assert!(run(0, "Sunny")
+            .unwrap_err()
+            .contains("its stderr was:\nstderr"));
+        assert_eq!(
+            run(2, "").unwrap(),
+            "The tool failed with exit code 2:\nstderr"
+        );
+
+        let tool = Arc::new(PluginTool {
+            path: PathBuf::from("/ask-tool-weather"),
+            definition: serde_json::from_str(DEFINITION).unwrap(),
+            runner: Arc::new(FakeCommandRunner::default().with_hang(&["/ask-tool-weather"])),
+        });
+        assert_eq!(
+            block_on(tool.run(serde_json::json!({}))).unwrap(),
+            format!("The tool failed: The command took more than {CALL_TIMEOUT:?}")
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Enforcement of the argument rules in the user's tool policy.
+
+use std::path::{Component, Path, PathBuf};
+
+use productivity_config::ToolRule;
+
+/// A field of a tool policy rule, tools declare which fields they enforce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleField {
+    AllowedPaths,
+    DeniedPaths,
+    AllowedUrls,
+    AllowedSubcommands,
+}
+
+impl RuleField {
+    /// The fields which restrict filesystem paths.
+    pub const PATHS: &[Self] = &[Self::AllowedPaths, Self::DeniedPaths];
+}
+
+impl std::fmt::Display for RuleField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AllowedPaths => write!(f, "allowed_paths"),
+            Self::DeniedPaths => write!(f, "denied_paths"),
+            Self::AllowedUrls => write!(f, "allowed_urls"),
+            Self::AllowedSubcommands => write!(f, "allowed_subcommands"),
+        }
+    }
+}
+
+/// Check that a tool enforces every field set in its rule, so a rule the tool would ignore refuses every invocation
+/// rather than letting them all through.
+pub fn check_enforced(rule: &ToolRule, enforced: &[RuleField]) -> Result<(), String> {
+    let unenforced: Vec<String> = [
+        (RuleField::AllowedPaths, rule.allowed_paths.is_empty()),
+        (RuleField::DeniedPaths, rule.denied_paths.is_empty()),
+        (RuleField::AllowedUrls, rule.allowed_urls.is_empty()),
+        (
+            RuleField::AllowedSubcommands,
+            rule.allowed_subcommands.is_empty(),
+        ),
+    ]
+    .into_iter()
+    .filter(|(field, is_empty)| !is_empty && !enforced.contains(field))
+    .map(|(field, _)| field.to_string())
+    .collect();
+    if unenforced.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "The tool can't enforce the {} of its tool policy rule, so it can't be used until they're removed from the rule",
+            unenforced.join(" and ")
+        ))
+    }
+}
+
+/// Build a glob set from a list of patterns.
+///
+/// Path patterns are expanded and the symlinks in the directories before their first wildcard are resolved, since paths
+/// are resolved before they are matched.
+fn build_glob_set(patterns: &[String], expand_paths: bool) -> Result<globset::GlobSet, String> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = if expand_paths {
+            resolve_pattern(&crate::path_utils::expand_path(pattern).map_err(|e| e.to_string())?)
+        } else {
+            pattern.clone()
+        };
+        let glob = globset::Glob::new(&pattern)
+            .map_err(|e| format!("The tool policy pattern {pattern} is invalid: {e}"))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("The tool policy patterns are invalid: {e}"))
+}
+
+/// Lexically resolve `.` and `..` components so paths can't escape an allowed directory.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Resolve the symlinks in a path so it can't escape an allowed directory through a link.
+///
+/// The part of the path which doesn't exist yet, such as a file which is about to be written, is resolved lexically.
+pub(super) fn resolve_path(path: &Path) -> PathBuf {
+    let components: Vec<Component> = path.components().collect();
+    for existing_components in (1..=components.len()).rev() {
+        let existing: PathBuf = components[..existing_components].iter().collect();
+        if let Ok(mut resolved) = existing.canonicalize() {
+            resolved.extend(&components[existing_components..]);
+            return normalize_path(&resolved);
+        }
+    }
+    normalize_path(path)
+}
+
+/// Resolve the directories of an absolute path pattern before its first wildcard.
+fn resolve_pattern(pattern: &str) -> String {
+    let path = Path::new(pattern);
+    if !path.is_absolute() {
+        return pattern.to_string();
+    }
+    let components: Vec<Component> = path.components().collect();
+    let first_wildcard = components
+        .iter()
+        .position(|component| {
+            component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '[', '{'])
+        })
+        .unwrap_or(components.len());
+    let mut resolved = resolve_path(&components[..first_wildcard].iter().collect::<PathBuf>());
+    resolved.extend(&components[first_wildcard..]);
+    resolved.to_string_lossy().to_string()
+}
+
+/// Check that filesystem paths are allowed by a rule.
+///
+/// Relative paths are resolved against the current directory and symlinks are resolved, a path is denied if either the
+/// path as given or the resolved path is denied, and it's only allowed if the resolved path is allowed.
+pub fn check_paths<'a>(
+    paths: impl IntoIterator<Item = &'a str>,
+    rule: &ToolRule,
+) -> Result<(), String> {
+    if rule.allowed_paths.is_empty() && rule.denied_paths.is_empty() {
+        return Ok(());
+    }
+
+    let allowed = build_glob_set(&rule.allowed_paths, true)?;
+    let denied = build_glob_set(&rule.denied_paths, true)?;
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let mut errors = vec![];
+    for path in paths {
+        let expanded = crate::path_utils::expand_path(path).map_err(|e| e.to_string())?;
+        let given_path = current_dir.join(expanded);
+        let full_path = resolve_path(&given_path);
+        if denied.is_match(&full_path) || denied.is_match(normalize_path(&given_path)) {
+            errors.push(format!(
+                "Access to {} is denied by the tool policy",
+                full_path.display()
+            ));
+        } else if !rule.allowed_paths.is_empty() && !allowed.is_match(&full_path) {
+            errors.push(format!(
+                "Access to {} is not allowed by the tool policy, the allowed paths are: {}",
+                full_path.display(),
+                rule.allowed_paths.join(", ")
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// The paths some rules allow, for tools which find the paths they read rather than being given them.
+#[derive(Clone)]
+pub struct PathFilter {
+    /// The union of the rules' allowed paths, `None` if none of them restrict the paths.
+    allowed: Option<globset::GlobSet>,
+    /// The union of the rules' denied paths.
+    denied: globset::GlobSet,
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        Self {
+            allowed: None,
+            denied: globset::GlobSet::empty(),
+        }
+    }
+}
+
+impl PathFilter {
+    /// Build the filter for the union of the allowed and denied paths of some rules.
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a ToolRule>) -> Result<Self, String> {
+        let mut allowed = vec![];
+        let mut denied = vec![];
+        for rule in rules {
+            allowed.extend(rule.allowed_paths.iter().cloned());
+            denied.extend(rule.denied_paths.iter().cloned());
+        }
+        Ok(Self {
+            allowed: if allowed.is_empty() {
+                None
+            } else {
+                Some(build_glob_set(&allowed, true)?)
+            },
+            denied: build_glob_set(&denied, true)?,
+        })
+    }
+
+    /// Check whether a resolved path is denied.
+    pub fn is_denied(&self, path: &Path) -> bool {
+        self.denied.is_match(path)
+    }
+
+    /// Check whether a resolved path is allowed, it must match an allowed path if there are any and not be denied.
+    pub fn is_allowed(&self, path: &Path) -> bool {
+        !self.is_denied(path)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.is_match(path))
+    }
+}
+
+/// Check that a URL is allowed by a rule.
+pub fn check_url(url: &str, rule: &ToolRule) -> Result<(), String> {
+    if rule.allowed_urls.is_empty() {
+        return Ok(());
+    }
+
+    if build_glob_set(&rule.allowed_urls, false)?.is_match(url) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Requests to {url} are not allowed by the tool policy, the allowed URLs are: {}",
+            rule.allowed_urls.join(", ")
+        ))
+    }
+}
+
+/// Check that the subcommand in a binary's arguments is allowed by a rule.
+///
+/// The subcommand must be the first argument, since without knowing which flags take values a flag's value can't be
+/// told apart from the subcommand.
+pub fn check_subcommand(arguments: &[String], rule: &ToolRule) -> Result<(), String> {
+    if rule.allowed_subcommands.is_empty() {
+        return Ok(());
+    }
+
+    match arguments.first() {
+        Some(subcommand) if rule.allowed_subcommands.contains(subcommand) => Ok(()),
+        Some(subcommand) if subcommand.starts_with('-') => Err(format!(
+            "The tool policy requires the first argument to be the subcommand rather than {subcommand}, the allowed subcommands are: {}",
+            rule.allowed_subcommands.join(", ")
+        )),
+        Some(subcommand) => Err(format!(
+            "The {subcommand} subcommand is not allowed by the tool policy, the allowed subcommands are: {}",
+            rule.allowed_subcommands.join(", ")
+        )),
+        None => Err(format!(
+            "A subcommand is required by the tool policy, the allowed subcommands are: {}",
+            rule.allowed_subcommands.join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create an empty directory for a test.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("ask-policy-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Check a single path.
+    fn check(path: &Path, rule: &ToolRule) -> Result<(), String> {
+        check_paths([path.to_str().unwrap()], rule)
+    }
+
+    #[test]
+    fn allowed_and_denied_paths() {
+        let directory = test_directory("globs");
+        let rule = ToolRule {
+            allowed_paths: vec![format!("{}/**", directory.display())],
+            denied_paths: vec!["**/.env".to_string()],
+            ..ToolRule::default()
+        };
+
+        assert_eq!(check(&directory.join("src/main.rs"), &rule), Ok(()));
+        assert!(check(&directory.join(".env"), &rule)
+            .unwrap_err()
+            .ends_with(".env is denied by the tool policy"));
+        assert!(check(Path::new("/etc/passwd"), &rule)
+            .unwrap_err()
+            .contains("is not allowed by the tool policy"));
+        assert_eq!(
+            check(Path::new("/etc/passwd"), &ToolRule::default()),
+            Ok(())
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn parent_directories_do_not_escape() {
+        let directory = test_directory("parents");
+        let rule = ToolRule {
+            allowed_paths: vec![format!("{}/**", directory.display())],
+            ..ToolRule::default()
+        };
+        assert_eq!(check(&directory.join("a/../b"), &rule), Ok(()));
+        assert!(check(&directory.join("../../etc/passwd"), &rule).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_do_not_escape() {
+        let directory = test_directory("symlinks");
+        std::fs::create_dir_all(directory.join("allowed")).unwrap();
+        std::fs::create_dir_all(directory.join("outside")).unwrap();
+        std::os::unix::fs::symlink(directory.join("outside"), directory.join("allowed/link"))
+            .unwrap();
+        let rule = ToolRule {
+            allowed_paths: vec![format!("{}/allowed/**", directory.display())],
+            denied_paths: vec![format!("{}/outside/secret", directory.display())],
+            ..ToolRule::default()
+        };
+
+        assert_eq!(check(&directory.join("allowed/new/file"), &rule), Ok(()));
+        assert!(check(&directory.join("allowed/link/file"), &rule)
+            .unwrap_err()
+            .contains("is not allowed by the tool policy"));
+        assert!(check(&directory.join("allowed/link/secret"), &rule)
+            .unwrap_err()
+            .contains("is denied by the tool policy"));
+        // `..` after a link is resolved from the link's target like the OS does, lexically this would be allowed
+        std::fs::write(directory.join("outside/file"), "").unwrap();
+        assert!(check(&directory.join("allowed/link/../outside/file"), &rule).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn urls() {
+        let rule = ToolRule {
+            allowed_urls: vec!["https://*.github.com/**".to_string()],
+            ..ToolRule::default()
+        };
+        assert_eq!(check_url("https://api.github.com/repos", &rule), Ok(()));
+        assert!(check_url("https://github.com.evil.com/", &rule).is_err());
+        assert!(check_url("http://api.github.com/repos", &rule).is_err());
+        assert_eq!(
+            check_url("http://example.com", &ToolRule::default()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unenforced_fields() {
+        let rule = ToolRule {
+            allowed_paths: vec!["/src/**".to_string()],
+            allowed_urls: vec!["https://**".to_string()],
+            ..ToolRule::default()
+        };
+        assert_eq!(check_enforced(&ToolRule::default(), &[]), Ok(()));
+        assert_eq!(
+            check_enforced(&rule, &[RuleField::AllowedPaths, RuleField::AllowedUrls]),
+            Ok(())
+        );
+        assert_eq!(
+            check_enforced(&rule, RuleField::PATHS),
+            Err("The tool can't enforce the allowed_urls of its tool policy rule, so it can't be used until they're removed from the rule".to_string())
+        );
+        assert!(check_enforced(&rule, &[])
+            .unwrap_err()
+            .contains("allowed_paths and allowed_urls"));
+    }
+
+    #[test]
+    fn subcommands() {
+        let rule = ToolRule {
+            allowed_subcommands: vec!["get".to_string()],
+            ..ToolRule::default()
+        };
+        let arguments = |arguments: &[&str]| -> Vec<String> {
+            arguments.iter().map(ToString::to_string).collect()
+        };
+
+        assert_eq!(
+            check_subcommand(&arguments(&["get", "pods"]), &rule),
+            Ok(())
+        );
+        assert!(check_subcommand(&arguments(&["delete", "pod"]), &rule)
+            .unwrap_err()
+            .starts_with("The delete subcommand is not allowed"));
+        // A flag's value can't be used to hide the subcommand
+        assert!(
+            check_subcommand(&arguments(&["-n", "get", "delete", "pod"]), &rule)
+                .unwrap_err()
+                .starts_with("The tool policy requires the first argument to be the subcommand")
+        );
+        assert!(check_subcommand(&[], &rule).is_err());
+        assert_eq!(
+            check_subcommand(&arguments(&["delete"]), &ToolRule::default()),
+            Ok(())
+        );
+    }
+}
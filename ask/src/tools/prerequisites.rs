@@ -0,0 +1,433 @@
+//! The prerequisites tools need to run and why they aren't satisfied.
+
+use std::sync::{Arc, LazyLock};
+use std::{future::Future, pin::Pin};
+
+use super::command_runner::{CommandRunner, CommandSpec, SystemCommandRunner};
+
+/// Matches a `major.minor[.patch]` version.
+static VERSION_PATTERN: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?").unwrap());
+
+/// How long to wait for a binary to print its version, some binaries such as wrappers which update themselves can hang.
+const VERSION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A check which depends on the tool's input, such as whether a Kubernetes context exists.
+pub type PrerequisiteCheck = Arc<
+    dyn Fn(&serde_json::Value) -> Pin<Box<dyn Future<Output = Result<(), PrerequisiteFailure>>>>,
+>;
+
+/// A binary which must be at least a certain version.
+#[derive(Clone, Debug)]
+pub struct MinimumVersion {
+    /// The binary, which is run with `--version` to find its version.
+    pub binary: String,
+    /// The minimum version such as `1.2` or `1.2.3`.
+    pub version: String,
+}
+
+/// A file or directory which must exist relative to a directory in the tool's input.
+#[derive(Clone, Debug)]
+pub struct InputPath {
+    /// The input field containing the directory, the current directory is used when the field is missing.
+    pub directory_field: String,
+    /// The path relative to the directory.
+    pub path: String,
+    /// How to create the path, `{directory}` is replaced with the directory.
+    pub hint: String,
+}
+
+/// Why a prerequisite isn't satisfied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrerequisiteFailure {
+    /// A binary isn't on `PATH`.
+    MissingBinary { binary: String },
+    /// A binary is older than the minimum version, or its version couldn't be found.
+    OutdatedBinary {
+        binary: String,
+        minimum: String,
+        found: Option<String>,
+    },
+    /// An environment variable isn't set.
+    MissingEnvironmentVariable { name: String },
+    /// A file or directory doesn't exist.
+    MissingPath { path: String, hint: String },
+    /// A custom check failed.
+    Failed {
+        message: String,
+        hint: Option<String>,
+    },
+}
+
+impl std::fmt::Display for PrerequisiteFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBinary { binary } => write!(f, "{binary} is not present on the system"),
+            Self::OutdatedBinary {
+                binary,
+                minimum,
+                found: Some(found),
+            } => write!(
+                f,
+                "{binary} {found} is installed but {minimum} or later is required"
+            ),
+            Self::OutdatedBinary {
+                binary,
+                minimum,
+                found: None,
+            } => write!(
+                f,
+                "The version of {binary} could not be found but {minimum} or later is required"
+            ),
+            Self::MissingEnvironmentVariable { name } => {
+                write!(f, "The environment variable {name} is not set")
+            }
+            Self::MissingPath { path, .. } => write!(f, "{path} does not exist"),
+            Self::Failed { message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl PrerequisiteFailure {
+    /// Get what to do to satisfy the prerequisite, if it's known.
+    pub fn get_hint(&self) -> Option<String> {
+        match self {
+            Self::MissingBinary { binary } => Some(format!(
+                "Install it with `{}`",
+                super::package_manager::get_install_command(std::slice::from_ref(binary))
+            )),
+            Self::OutdatedBinary { binary, .. } => Some(format!(
+                "Upgrade it with `{}`",
+                super::package_manager::get_install_command(std::slice::from_ref(binary))
+            )),
+            Self::MissingEnvironmentVariable { name } => {
+                Some(format!("Set {name} before running `ask`"))
+            }
+            Self::MissingPath { hint, .. } => Some(hint.clone()),
+            Self::Failed { hint, .. } => hint.clone(),
+        }
+    }
+}
+
+/// Format failures for the LLM or the user, one per line with their hints.
+pub fn describe_failures(failures: &[PrerequisiteFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| match failure.get_hint() {
+            Some(hint) => format!("{failure} - {hint}"),
+            None => failure.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The prerequisites required to run a tool.
+///
+/// Binaries, versions, and environment variables are checked before the tool is offered to the LLM, paths and custom
+/// checks are checked against the input of each invocation.
+#[derive(Clone, Default)]
+pub struct ToolPrerequisites {
+    /// The binaries that must be available on the system for the tool to run.
+    pub binaries: Vec<String>,
+    /// The binaries which must be at least a certain version.
+    pub minimum_versions: Vec<MinimumVersion>,
+    /// The environment variables which must be set.
+    pub environment_variables: Vec<String>,
+    /// The files or directories which must exist relative to the input.
+    pub input_paths: Vec<InputPath>,
+    /// Checks of the input which can't be expressed declaratively.
+    pub checks: Vec<PrerequisiteCheck>,
+}
+
+/// Parse the first `major.minor[.patch]` version in some text, a missing patch version is 0.
+fn parse_version(text: &str) -> Option<Vec<u64>> {
+    let captures = VERSION_PATTERN.captures(text)?;
+    let mut version: Vec<u64> = captures
+        .iter()
+        .skip(1)
+        .flatten()
+        .filter_map(|part| part.as_str().parse().ok())
+        .collect();
+    version.resize(3, 0);
+    Some(version)
+}
+
+/// Get the version of a binary from its `--version` output, `None` if it can't be found or the binary doesn't print it in
+/// time.
+fn get_binary_version(runner: &dyn CommandRunner, binary: &str) -> Option<String> {
+    let output = runner
+        .run(
+            &CommandSpec::new(binary)
+                .arg("--version")
+                .timeout(VERSION_TIMEOUT),
+        )
+        .ok()?;
+    // Some binaries print their version to stderr
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    parse_version(&text).map(|version| {
+        version
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    })
+}
+
+impl ToolPrerequisites {
+    /// Get the binaries which aren't present on the system.
+    pub fn get_missing_binaries(&self) -> Vec<String> {
+        self.binaries
+            .iter()
+            .filter(|binary| which::which(binary).is_err())
+            .cloned()
+            .collect()
+    }
+
+    /// Check the prerequisites which don't depend on the input.
+    pub fn is_satisfied(&self) -> Result<(), Vec<PrerequisiteFailure>> {
+        self.is_satisfied_with(&SystemCommandRunner)
+    }
+
+    /// Check the prerequisites which don't depend on the input, running binaries to find their versions with `runner`.
+    fn is_satisfied_with(
+        &self,
+        runner: &dyn CommandRunner,
+    ) -> Result<(), Vec<PrerequisiteFailure>> {
+        let missing = self.get_missing_binaries();
+        let mut failures: Vec<PrerequisiteFailure> = missing
+            .iter()
+            .map(|binary| PrerequisiteFailure::MissingBinary {
+                binary: binary.clone(),
+            })
+            .collect();
+
+        for minimum in &self.minimum_versions {
+            if missing.contains(&minimum.binary) {
+                continue;
+            }
+            let found = get_binary_version(runner, &minimum.binary);
+            let is_new_enough = found
+                .as_deref()
+                .and_then(parse_version)
+                .zip(parse_version(&minimum.version))
+                .is_some_and(|(found, minimum)| found >= minimum);
+            if !is_new_enough {
+                failures.push(PrerequisiteFailure::OutdatedBinary {
+                    binary: minimum.binary.clone(),
+                    minimum: minimum.version.clone(),
+                    found,
+                });
+            }
+        }
+
+        failures.extend(
+            self.environment_variables
+                .iter()
+                .filter(|name| std::env::var_os(name).is_none_or(|value| value.is_empty()))
+                .map(|name| PrerequisiteFailure::MissingEnvironmentVariable { name: name.clone() }),
+        );
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+
+    /// Check the prerequisites which depend on the input of an invocation.
+    pub async fn check_input(
+        &self,
+        input: &serde_json::Value,
+    ) -> Result<(), Vec<PrerequisiteFailure>> {
+        let mut failures = vec![];
+        for input_path in &self.input_paths {
+            let directory = input
+                .get(&input_path.directory_field)
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default();
+            // Invalid directories are reported by the tool itself
+            let Ok(directory) = crate::path_utils::expand_path(directory) else {
+                continue;
+            };
+            let path = std::path::Path::new(&directory).join(&input_path.path);
+            if !path.exists() {
+                let shown_directory = if directory.is_empty() {
+                    "the current directory"
+                } else {
+                    &directory
+                };
+                failures.push(PrerequisiteFailure::MissingPath {
+                    path: path.display().to_string(),
+                    hint: input_path.hint.replace("{directory}", shown_directory),
+                });
+            }
+        }
+
+        for check in &self.checks {
+            if let Err(failure) = check(input).await {
+                failures.push(failure);
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::{block_on, FakeCommandRunner};
+
+    /// Require a minimum version of a binary.
+    fn minimum_version(binary: &str, version: &str) -> ToolPrerequisites {
+        ToolPrerequisites {
+            minimum_versions: vec![MinimumVersion {
+                binary: binary.to_string(),
+                version: version.to_string(),
+            }],
+            ..ToolPrerequisites::default()
+        }
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(
+            parse_version("Terraform v1.5.7\non linux_amd64"),
+            Some(vec![1, 5, 7])
+        );
+        assert_eq!(parse_version("jq-1.7"), Some(vec![1, 7, 0]));
+        assert_eq!(parse_version("version 10.20.30.40"), Some(vec![10, 20, 30]));
+        assert_eq!(parse_version("git 2"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn compares_versions() {
+        let check = |stdout: &str, stderr: &str, minimum: &str| {
+            let runner = FakeCommandRunner::default().with_output(
+                &["terraform", "--version"],
+                0,
+                stdout,
+                stderr,
+            );
+            let result = minimum_version("terraform", minimum).is_satisfied_with(&runner);
+            assert_eq!(runner.get_commands()[0].arguments, ["--version"]);
+            result
+        };
+
+        assert!(check("Terraform v1.5.7", "", "1.5").is_ok());
+        assert!(check("Terraform v1.10.0", "", "1.9.1").is_ok());
+        // Some binaries print their version to stderr
+        assert!(check("", "terraform 2.0", "1.5").is_ok());
+        assert_eq!(
+            check("Terraform v1.4.9", "", "1.5"),
+            Err(vec![PrerequisiteFailure::OutdatedBinary {
+                binary: "terraform".to_string(),
+                minimum: "1.5".to_string(),
+                found: Some("1.4.9".to_string()),
+            }])
+        );
+        assert_eq!(
+            check("unknown", "", "1.5"),
+            Err(vec![PrerequisiteFailure::OutdatedBinary {
+                binary: "terraform".to_string(),
+                minimum: "1.5".to_string(),
+                found: None,
+            }])
+        );
+        // Binaries which can't be run have no version
+        assert!(minimum_version("terraform", "1.5")
+            .is_satisfied_with(&FakeCommandRunner::default())
+            .is_err());
+    }
+
+    #[test]
+    fn times_out_finding_versions() {
+        let runner = FakeCommandRunner::default().with_hang(&["terraform", "--version"]);
+        assert_eq!(
+            minimum_version("terraform", "1.5").is_satisfied_with(&runner),
+            Err(vec![PrerequisiteFailure::OutdatedBinary {
+                binary: "terraform".to_string(),
+                minimum: "1.5".to_string(),
+                found: None,
+            }])
+        );
+        assert_eq!(runner.get_commands()[0].timeout, Some(VERSION_TIMEOUT));
+    }
+
+    #[test]
+    fn checks_input_paths() {
+        let directory =
+            std::env::temp_dir().join(format!("ask-prerequisites-test-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join(".terraform")).unwrap();
+        let prerequisites = ToolPrerequisites {
+            input_paths: vec![InputPath {
+                directory_field: "directory".to_string(),
+                path: ".terraform".to_string(),
+                hint: "Run `terraform init` in {directory}".to_string(),
+            }],
+            ..ToolPrerequisites::default()
+        };
+
+        let present = block_on(
+            prerequisites.check_input(&serde_json::json!({ "directory": directory.to_str() })),
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+        let missing = block_on(
+            prerequisites.check_input(&serde_json::json!({ "directory": directory.to_str() })),
+        );
+
+        assert!(present.is_ok());
+        assert_eq!(
+            missing,
+            Err(vec![PrerequisiteFailure::MissingPath {
+                path: directory.join(".terraform").display().to_string(),
+                hint: format!("Run `terraform init` in {}", directory.display()),
+            }])
+        );
+        // Without the field the current directory is checked
+        let failures = block_on(prerequisites.check_input(&serde_json::json!({}))).unwrap_err();
+        assert_eq!(
+            failures[0].get_hint().as_deref(),
+            Some("Run `terraform init` in the current directory")
+        );
+    }
+
+    #[test]
+    fn describes_failures() {
+        let failures = [
+            PrerequisiteFailure::MissingEnvironmentVariable {
+                name: "KUBECONFIG".to_string(),
+            },
+            PrerequisiteFailure::Failed {
+                message: "No context".to_string(),
+                hint: None,
+            },
+            PrerequisiteFailure::OutdatedBinary {
+                binary: "jq".to_string(),
+                minimum: "1.7".to_string(),
+                found: None,
+            },
+        ];
+        let description = describe_failures(&failures);
+        let lines: Vec<&str> = description.lines().collect();
+        assert_eq!(
+            lines[..2],
+            [
+                "The environment variable KUBECONFIG is not set - Set KUBECONFIG before running `ask`",
+                "No context",
+            ]
+        );
+        assert!(lines[2].starts_with(
+            "The version of jq could not be found but 1.7 or later is required - Upgrade it with `"
+        ));
+    }
+}
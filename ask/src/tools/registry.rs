@@ -0,0 +1,209 @@
+//! The set of tools offered to the LLM during a session.
+
+use std::sync::Arc;
+
+use super::{Tool, ToolDefinition};
+
+/// Owns the tools of a session and their definitions, in registration order.
+#[derive(Default)]
+pub struct ToolRegistry {
+    /// The tools and their definitions, definitions are generated once since generating schemas isn't free.
+    tools: Vec<(ToolDefinition, Arc<dyn Tool>)>,
+}
+
+impl ToolRegistry {
+    /// Register tools in order, skipping tools with the same name as an earlier tool.
+    pub fn from_tools(tools: impl IntoIterator<Item = Arc<dyn Tool>>) -> Self {
+        let mut registry = Self::default();
+        for tool in tools {
+            if let Err(e) = registry.register(tool) {
+                tracing::warn!("Skipping a tool: {e}");
+            }
+        }
+        registry
+    }
+
+    /// Register the tools of each source in order of precedence: the Rust tools, the tools declared in the config, the
+    /// plugins, and then the tools of the MCP servers.
+    pub fn from_sources(
+        rust_tools: Vec<Arc<dyn Tool>>,
+        custom_tools: Vec<Arc<dyn Tool>>,
+        plugin_tools: Vec<Arc<dyn Tool>>,
+        mcp_tools: Vec<Arc<dyn Tool>>,
+    ) -> Self {
+        Self::from_tools(
+            rust_tools
+                .into_iter()
+                .chain(custom_tools)
+                .chain(plugin_tools)
+                .chain(mcp_tools),
+        )
+    }
+
+    /// Register a tool, failing if a tool with the same name is already registered.
+    pub fn register(&mut self, tool: Arc<dyn Tool>) -> anyhow::Result<()> {
+        let definition = tool.get_definition();
+        if self.get(&definition.name).is_some() {
+            anyhow::bail!("A tool named {} is already registered", definition.name);
+        }
+        self.tools.push((definition, tool));
+        Ok(())
+    }
+
+    /// Get a tool by name.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools
+            .iter()
+            .find(|(definition, _)| definition.name == name)
+            .map(|(_, tool)| tool.clone())
+    }
+
+    /// Get the definitions of the tools to send to the LLM.
+    pub fn definitions(&self) -> impl Iterator<Item = &ToolDefinition> {
+        self.tools.iter().map(|(definition, _)| definition)
+    }
+
+    /// Get the tools with their definitions.
+    pub fn iter(&self) -> impl Iterator<Item = (&ToolDefinition, &Arc<dyn Tool>)> {
+        self.tools
+            .iter()
+            .map(|(definition, tool)| (definition, tool))
+    }
+
+    /// Only keep the tools for which `keep` returns true.
+    pub fn retain(&mut self, mut keep: impl FnMut(&ToolDefinition, &Arc<dyn Tool>) -> bool) {
+        self.tools
+            .retain(|(definition, tool)| keep(definition, tool));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, pin::Pin};
+
+    use super::*;
+
+    /// A tool which only has a name and a description.
+    struct NamedTool {
+        name: &'static str,
+        description: &'static str,
+    }
+
+    impl Tool for NamedTool {
+        fn get_definition(&self) -> ToolDefinition {
+            ToolDefinition {
+                name: self.name.to_string(),
+                description: self.description.to_string(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }
+        }
+
+        fn run(
+            self: Arc<Self>,
+            _input: serde_json::Value,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>>>> {
+            Box::pin(async move { Ok(self.description.to_string()) })
+        }
+    }
+
+    /// Create a tool from its name and description.
+    fn tool(name: &'static str, description: &'static str) -> Arc<dyn Tool> {
+        Arc::new(NamedTool { name, description })
+    }
+
+    /// Get the names of the registered tools.
+    fn names(registry: &ToolRegistry) -> Vec<&str> {
+        registry
+            .definitions()
+            .map(|definition| definition.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let mut registry = ToolRegistry::default();
+        registry.register(tool("weather", "first")).unwrap();
+        let error = registry.register(tool("weather", "second")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "A tool named weather is already registered"
+        );
+        assert_eq!(
+            registry
+                .get("weather")
+                .unwrap()
+                .get_definition()
+                .description,
+            "first"
+        );
+    }
+
+    #[test]
+    fn looks_up_and_filters_tools() {
+        let mut registry =
+            ToolRegistry::from_tools([tool("a", "A"), tool("b", "B"), tool("c", "C")]);
+        assert_eq!(registry.get("b").unwrap().get_definition().description, "B");
+        assert!(registry.get("d").is_none());
+        assert!(registry
+            .iter()
+            .all(|(definition, tool)| tool.get_definition().name == definition.name));
+
+        registry.retain(|definition, _| definition.name != "b");
+        assert_eq!(names(&registry), ["a", "c"]);
+        assert!(registry.get("b").is_none());
+    }
+
+    #[test]
+    fn earlier_sources_take_precedence() {
+        let custom_tools = super::super::custom_tool::get_custom_tools(&[
+            productivity_config::CustomToolConfig {
+                name: "read_files".to_string(),
+                description: "A custom tool shadowing a built in tool".to_string(),
+                binary: "cat".to_string(),
+                argument_prefix: vec![],
+                argument_template: vec![],
+                parameters: serde_json::Value::Null,
+                output_mode: productivity_config::CustomToolOutputMode::Output,
+                print_to_console: false,
+            },
+            productivity_config::CustomToolConfig {
+                name: "git_log".to_string(),
+                description: "custom".to_string(),
+                binary: "git".to_string(),
+                argument_prefix: vec!["log".to_string()],
+                argument_template: vec![],
+                parameters: serde_json::Value::Null,
+                output_mode: productivity_config::CustomToolOutputMode::Output,
+                print_to_console: false,
+            },
+        ]);
+        let built_in_tools = super::super::rust_tools::get_rust_tools();
+        let built_in_count = built_in_tools.len();
+        let registry = ToolRegistry::from_sources(
+            built_in_tools,
+            custom_tools,
+            vec![tool("git_log", "plugin"), tool("weather", "plugin")],
+            vec![tool("weather", "MCP"), tool("github_search", "MCP")],
+        );
+
+        let names = names(&registry);
+        assert_eq!(names[0], "cloud_context");
+        assert_eq!(names[1], "read_files");
+        assert_eq!(
+            names[built_in_count..],
+            ["git_log", "weather", "github_search"]
+        );
+        assert_ne!(
+            registry
+                .get("read_files")
+                .unwrap()
+                .get_definition()
+                .description,
+            "A custom tool shadowing a built in tool"
+        );
+        let description = |name| registry.get(name).unwrap().get_definition().description;
+        assert!(description("git_log").starts_with("custom\n"));
+        assert_eq!(description("weather"), "plugin");
+        assert_eq!(description("github_search"), "MCP");
+    }
+}
@@ -0,0 +1,442 @@
+//! Tool to search the content of files.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use super::RustTool;
+
+/// Tool to find the lines of files which match a pattern.
+pub struct SearchFilesTool;
+
+/// Input to the search files tool.
+#[derive(serde::Deserialize, schemars::JsonSchema, Debug)]
+pub struct SearchFilesInput {
+    /// The directory to search recursively, or a single file to search.
+    root_directory: String,
+    /// A short justification for why you want to search the directory.
+    reason: String,
+    /// The regular expression to search for, using Rust's regex syntax. Each line is matched separately.
+    pattern: String,
+    /// Match `pattern` as literal text rather than as a regular expression.
+    #[serde(default)]
+    literal: bool,
+    /// Ignore case when matching `pattern`.
+    #[serde(default)]
+    case_insensitive: bool,
+    /// Only search files whose path relative to `root_directory` matches one of these globs, such as `*.rs` or `src/**`.
+    /// All files are searched when this is empty.
+    #[serde(default)]
+    include_globs: Vec<String>,
+    /// Don't search files whose path relative to `root_directory` matches one of these globs, such as `*.lock`.
+    #[serde(default)]
+    exclude_globs: Vec<String>,
+    /// The number of lines to return before and after each matching line. Defaults to 2, at most 10 are returned.
+    context_lines: Option<usize>,
+    /// The maximum number of matching lines to return. Defaults to 50, at most 500 are returned regardless.
+    max_results: Option<usize>,
+}
+
+/// The number of context lines to return around each match by default.
+const DEFAULT_CONTEXT_LINES: usize = 2;
+
+/// The maximum number of context lines to return around each match.
+const MAX_CONTEXT_LINES: usize = 10;
+
+/// The number of matching lines to return by default.
+const DEFAULT_RESULTS: usize = 50;
+
+/// The maximum number of matching lines to return in one invocation.
+const MAX_RESULTS: usize = 500;
+
+/// Files larger than this are skipped, they are rarely source code.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Lines longer than this are cut short, such as the lines of minified files.
+const MAX_LINE_CHARS: usize = 300;
+
+/// Which files to search.
+struct FileFilter {
+    /// Globs relative to the root of which a file must match one, `None` to search all files.
+    include: Option<globset::GlobSet>,
+    /// Globs relative to the root of which a file must match none.
+    exclude: globset::GlobSet,
+    /// The full paths the tool policy of this tool and of `read_files` allow.
+    policy: super::policy::PathFilter,
+}
+
+/// Build a glob set from the globs in the input.
+fn build_glob_set(globs: &[String]) -> Result<globset::GlobSet, String> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for glob in globs {
+        builder
+            .add(globset::Glob::new(glob).map_err(|e| format!("The glob {glob} is invalid: {e}"))?);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("The globs are invalid: {e}"))
+}
+
+/// Cut a line short if it's too long to be useful.
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Format the matching lines of a file with their context like `grep`, `:` follows the numbers of matching lines and
+/// `-` follows the numbers of context lines.
+fn format_matches(lines: &[&str], matches: &[usize], context_lines: usize) -> String {
+    let mut result = String::new();
+    let mut next_line = 0;
+    for (index, &line) in matches.iter().enumerate() {
+        let start = line.saturating_sub(context_lines).max(next_line);
+        if next_line > 0 && start > next_line {
+            result.push_str("--\n");
+        }
+        // Extend the context to the next match's context if they overlap
+        let end = (line + context_lines).min(lines.len() - 1);
+        let end = match matches.get(index + 1) {
+            Some(&next) if next.saturating_sub(context_lines) <= end + 1 => next - 1,
+            _ => end,
+        };
+        for (number, content) in lines.iter().enumerate().take(end + 1).skip(start) {
+            let separator = if number == line { ':' } else { '-' };
+            result.push_str(&format!(
+                "{}{separator}{}\n",
+                number + 1,
+                truncate_line(content)
+            ));
+        }
+        next_line = end + 1;
+    }
+    result
+}
+
+/// Search the files under a directory for lines matching a pattern, respecting `.gitignore` and `.ignore` files.
+fn search(
+    root: &Path,
+    pattern: &regex::Regex,
+    filter: &FileFilter,
+    context_lines: usize,
+    max_results: usize,
+) -> String {
+    let walk = ignore::WalkBuilder::new(root)
+        // Hidden files such as `.env` are often what the user is asking about
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(std::ffi::OsStr::cmp)
+        .build();
+
+    let mut result = String::new();
+    let mut result_count = 0;
+    let mut file_count = 0;
+    let mut is_capped = false;
+    let mut binary_files = 0;
+    let mut large_files = 0;
+    let mut denied_files = 0;
+    let mut errors = vec![];
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+
+        let path = entry.path();
+        // A single file is matched by its name
+        let relative_path = path
+            .strip_prefix(root)
+            .ok()
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new(entry.file_name()));
+        if filter
+            .include
+            .as_ref()
+            .is_some_and(|include| !include.is_match(relative_path))
+            || filter.exclude.is_match(relative_path)
+        {
+            continue;
+        }
+        if !filter.policy.is_allowed(path) {
+            denied_files += 1;
+            continue;
+        }
+        if entry
+            .metadata()
+            .is_ok_and(|metadata| metadata.len() > MAX_FILE_BYTES)
+        {
+            large_files += 1;
+            continue;
+        }
+
+        let content = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(format!("{}: {e}", path.display()));
+                continue;
+            }
+        };
+        if super::filesystem::detect_binary(
+            &content[..content.len().min(super::filesystem::BINARY_SAMPLE_BYTES)],
+        )
+        .is_some()
+        {
+            binary_files += 1;
+            continue;
+        }
+        let text = String::from_utf8_lossy(&content);
+        let lines: Vec<&str> = text.lines().collect();
+        let matches: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| pattern.is_match(line))
+            .map(|(number, _)| number)
+            .take(max_results - result_count + 1)
+            .collect();
+        if matches.is_empty() {
+            continue;
+        }
+
+        // A match past the limit shows the results are capped
+        let remaining = max_results - result_count;
+        is_capped = matches.len() > remaining;
+        let matches = &matches[..matches.len().min(remaining)];
+        if !matches.is_empty() {
+            result_count += matches.len();
+            file_count += 1;
+            result.push_str(&format!(
+                "<file path=\"{}\">\n{}</file>\n",
+                path.display(),
+                format_matches(&lines, matches, context_lines)
+            ));
+        }
+        if is_capped {
+            break;
+        }
+    }
+
+    if result_count == 0 {
+        result.push_str("No matching lines were found.\n");
+    } else if is_capped {
+        result.push_str(&format!(
+            "Only the first {max_results} matching lines were returned, narrow the pattern or the globs, or increase `max_results` to see more.\n"
+        ));
+    } else {
+        result.push_str(&format!(
+            "Found {result_count} matching lines in {file_count} files.\n"
+        ));
+    }
+    if binary_files + large_files > 0 {
+        result.push_str(&format!(
+            "{binary_files} binary files and {large_files} files larger than 1 MiB were not searched.\n"
+        ));
+    }
+    if denied_files > 0 {
+        result.push_str(&format!(
+            "{denied_files} files were not searched since the tool policy doesn't allow reading them.\n"
+        ));
+    }
+    if !errors.is_empty() {
+        result.push_str(&format!(
+            "Some files could not be searched:\n{}\n",
+            errors.join("\n")
+        ));
+    }
+    result
+}
+
+impl RustTool for SearchFilesTool {
+    type Input = SearchFilesInput;
+
+    fn get_name(&self) -> String {
+        "search_files".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        r" Search the files in a directory on the user's computer for lines matching a regular expression or literal text, for example to find where something is defined or used.
+            The user will be prompted to accept or deny the search to preserve their privacy, in the same way as reading files.
+            Files ignored by `.gitignore` and `.ignore` files, binary files, and files larger than 1 MiB aren't searched.
+            The tool returns XML `file` tags with a `path` attribute for each file with matches, containing the matching lines and the lines around them.
+            Each line starts with its line number followed by `:` for matching lines or `-` for context lines, and `--` separates groups of lines which aren't adjacent.
+            Use `read_files` with `read_options` to read more of a file around a match.
+        "
+        .trim()
+        .to_string()
+    }
+
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        super::policy::RuleField::PATHS
+    }
+
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        super::policy::check_paths([input.root_directory.as_str()], rule)
+    }
+
+    async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
+        let pattern = if input.literal {
+            regex::escape(&input.pattern)
+        } else {
+            input.pattern
+        };
+        let pattern = match regex::RegexBuilder::new(&pattern)
+            .case_insensitive(input.case_insensitive)
+            .build()
+        {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                return Ok(format!(
+                    "The pattern is not a valid regular expression: {e}"
+                ))
+            }
+        };
+
+        let config = productivity_config::Config::get_or_default()?;
+        // The tool policy checks the root, files under it must also be allowed by this tool's rule and by `read_files`'
+        // rule since searching returns the content of files
+        let policy = super::policy::PathFilter::new(
+            [self.get_name().as_str(), "read_files"]
+                .iter()
+                .filter_map(|name| config.tool_policy.rules.get(*name)),
+        )
+        .map_err(anyhow::Error::msg)?;
+        let filter = FileFilter {
+            include: if input.include_globs.is_empty() {
+                None
+            } else {
+                match build_glob_set(&input.include_globs) {
+                    Ok(include) => Some(include),
+                    Err(e) => return Ok(e),
+                }
+            },
+            exclude: match build_glob_set(&input.exclude_globs) {
+                Ok(exclude) => exclude,
+                Err(e) => return Ok(e),
+            },
+            policy,
+        };
+
+        let current_dir = std::env::current_dir().context("Failed to get the current directory")?;
+        let root = super::policy::resolve_path(
+            &current_dir.join(crate::path_utils::expand_path(&input.root_directory)?),
+        );
+        let root_string = root.to_string_lossy().to_string();
+        let access = super::file_access::request_access(
+            super::file_access::AccessKind::Search,
+            &[(root_string.clone(), input.reason)],
+        )?;
+        if !access.denied.is_empty() {
+            return Ok(access.describe_denied());
+        }
+        if !root.exists() {
+            return Ok(format!("{root_string} does not exist."));
+        }
+
+        Ok(search(
+            &root,
+            &pattern,
+            &filter,
+            input
+                .context_lines
+                .unwrap_or(DEFAULT_CONTEXT_LINES)
+                .min(MAX_CONTEXT_LINES),
+            input
+                .max_results
+                .unwrap_or(DEFAULT_RESULTS)
+                .clamp(1, MAX_RESULTS),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_overlapping_context() {
+        let lines = ["a", "b", "match", "c", "match", "d", "e", "f", "g", "match"];
+        assert_eq!(
+            format_matches(&lines, &[2, 4, 9], 1),
+            "2-b\n3:match\n4-c\n5:match\n6-d\n--\n9-g\n10:match\n"
+        );
+    }
+
+    #[test]
+    fn searches_files_respecting_filters() {
+        let root = std::env::temp_dir().join(format!("ask-search-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        std::fs::write(root.join("notes.md"), "Call run() to start\n").unwrap();
+        std::fs::write(root.join("data.bin"), b"run\0\x01").unwrap();
+
+        let filter = FileFilter {
+            include: None,
+            exclude: build_glob_set(&["*.md".to_string()]).unwrap(),
+            policy: super::super::policy::PathFilter::default(),
+        };
+        let pattern = regex::Regex::new(&regex::escape("run()")).unwrap();
+        let result = search(&root, &pattern, &filter, 1, 10);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            result,
+            format!(
+                "<file path=\"{}\">\n1-fn main() {{\n2:    run();\n3-}}\n</file>\nFound 1 matching lines in 1 files.\n1 binary files and 0 files larger than 1 MiB were not searched.\n",
+                root.join("src/main.rs").display()
+            )
+        );
+    }
+
+    #[test]
+    fn only_searches_files_the_policy_allows() {
+        let root =
+            std::env::temp_dir().join(format!("ask-search-policy-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("other")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "run();\n").unwrap();
+        std::fs::write(root.join("src/.env"), "run()=secret\n").unwrap();
+        std::fs::write(root.join("other/notes.txt"), "run() later\n").unwrap();
+        let root = super::super::policy::resolve_path(&root);
+
+        let rules = [
+            productivity_config::ToolRule {
+                allowed_paths: vec![format!("{}/src/**", root.display())],
+                ..productivity_config::ToolRule::default()
+            },
+            productivity_config::ToolRule {
+                denied_paths: vec!["**/.env".to_string()],
+                ..productivity_config::ToolRule::default()
+            },
+        ];
+        let filter = FileFilter {
+            include: None,
+            exclude: globset::GlobSet::empty(),
+            policy: super::super::policy::PathFilter::new(&rules).unwrap(),
+        };
+        let pattern = regex::Regex::new(&regex::escape("run()")).unwrap();
+        let result = search(&root, &pattern, &filter, 0, 10);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            result,
+            format!(
+                "<file path=\"{}\">\n1:run();\n</file>\nFound 1 matching lines in 1 files.\n2 files were not searched since the tool policy doesn't allow reading them.\n",
+                root.join("src/main.rs").display()
+            )
+        );
+        assert!(!result.contains("secret"));
+    }
+}
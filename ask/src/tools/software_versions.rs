@@ -83,6 +83,8 @@ impl RustTool for SoftwareVersionsTool {
 
     async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
         let mut result = Vec::with_capacity(input.libraries.len());
+        // The progress bar replaces the status spinner
+        crate::status::clear();
         let progress = indicatif::ProgressBar::new(input.libraries.len() as u64).with_style(
             indicatif::ProgressStyle::with_template("{msg} {wide_bar} {pos}/{len}").unwrap(),
         );
@@ -1,7 +1,8 @@
-use anyhow::Context;
+use std::sync::Arc;
 
+use super::command_runner::{CommandRunner, CommandSpec, SystemCommandRunner};
 use super::RustTool;
-use crate::tools::ToolPrerequisites;
+use crate::tools::{InputPath, MinimumVersion, ToolPrerequisites};
 
 /// Input to the Terraform plan command.
 #[derive(serde::Deserialize, schemars::JsonSchema)]
@@ -11,7 +12,17 @@ pub struct TerraformPlanInput {
 }
 
 /// A tool that wraps the `terraform plan` command.
-pub struct TerraformPlanTool;
+pub struct TerraformPlanTool {
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl TerraformPlanTool {
+    pub fn new() -> Self {
+        Self {
+            runner: Arc::new(SystemCommandRunner),
+        }
+    }
+}
 
 impl RustTool for TerraformPlanTool {
     type Input = TerraformPlanInput;
@@ -24,31 +35,50 @@ impl RustTool for TerraformPlanTool {
         "Run `terraform plan` in the specified directory and returns the output plan. This tool can be used to find out if cloud deployments are up to date.".to_string()
     }
 
+    fn get_enforced_rule_fields(&self) -> &'static [super::policy::RuleField] {
+        super::policy::RuleField::PATHS
+    }
+
+    fn check_policy(
+        &self,
+        input: &Self::Input,
+        rule: &productivity_config::ToolRule,
+    ) -> Result<(), String> {
+        // Terraform runs in the current directory when no directory is given
+        super::policy::check_paths([input.working_directory.as_deref().unwrap_or(".")], rule)
+    }
+
     fn get_prequisites(&self) -> ToolPrerequisites {
         ToolPrerequisites {
             binaries: vec!["terraform".to_string()],
+            // `-chdir` was added in 0.14
+            minimum_versions: vec![MinimumVersion {
+                binary: "terraform".to_string(),
+                version: "0.14".to_string(),
+            }],
+            input_paths: vec![InputPath {
+                directory_field: "working_directory".to_string(),
+                path: ".terraform".to_string(),
+                hint: "Run `terraform init` in {directory} first".to_string(),
+            }],
+            ..Default::default()
         }
     }
 
-    async fn run(self: std::sync::Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
-        let mut base_command = std::process::Command::new("terraform");
+    async fn run(self: Arc<Self>, input: Self::Input) -> anyhow::Result<String> {
         let working_directory = crate::path_utils::expand_path(
             input.working_directory.clone().unwrap_or_default().as_str(),
         )?;
-        let output = base_command
-            .arg(format!("-chdir={working_directory}"))
-            .arg("plan")
-            .arg("-detailed-exitcode")
-            .arg("-no-color")
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .output()
-            .context("Failed to execute terraform plan")?;
+        let output = self.runner.run(
+            &CommandSpec::new("terraform")
+                .arg(format!("-chdir={working_directory}"))
+                .args(["plan", "-detailed-exitcode", "-no-color"]),
+        )?;
 
         // 0 - Succeeded, diff is empty (no changes)
         // 1 - Errored
         // 2 - Succeeded, there is a diff
-        let status = output.status.code().unwrap_or(1);
+        let status = output.code.unwrap_or(1);
         Ok(match status {
             0 => "The infrastructure is up to date, terraform has no changes to deploy".to_string(),
             2 => format!("The infrastructure has changes, terraform has a plan to deploy:\n<stdout>\n{}\n</stdout>", String::from_utf8_lossy(&output.stdout)),
@@ -56,3 +86,87 @@ impl RustTool for TerraformPlanTool {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::command_runner::fake::{block_on, FakeCommandRunner};
+
+    /// Run a plan in `infra` with `terraform` exiting with a code.
+    fn plan(code: i32) -> String {
+        let tool = Arc::new(TerraformPlanTool {
+            runner: Arc::new(FakeCommandRunner::default().with_output(
+                &[
+                    "terraform",
+                    "-chdir=infra",
+                    "plan",
+                    "-detailed-exitcode",
+                    "-no-color",
+                ],
+                code,
+                "the plan",
+                "the error",
+            )),
+        });
+        let input = TerraformPlanInput {
+            working_directory: Some("infra".to_string()),
+        };
+        block_on(RustTool::run(tool, input)).unwrap()
+    }
+
+    #[test]
+    fn no_changes() {
+        assert_eq!(
+            plan(0),
+            "The infrastructure is up to date, terraform has no changes to deploy"
+        );
+    }
+
+    #[test]
+    fn changes() {
+        assert_eq!(
+            plan(2),
+            "The infrastructure has changes, terraform has a plan to deploy:\n<stdout>\nthe plan\n</stdout>"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            plan(1),
+            "An error occurred while running terraform plan:\n<stderr>\nthe error\n</stderr>"
+        );
+    }
+
+    #[test]
+    fn terraform_fails_to_start() {
+        let tool = Arc::new(TerraformPlanTool {
+            runner: Arc::new(FakeCommandRunner::default()),
+        });
+        let input = TerraformPlanInput {
+            working_directory: None,
+        };
+        assert!(block_on(RustTool::run(tool, input)).is_err());
+    }
+
+    #[test]
+    fn checks_the_working_directory() {
+        let rule = productivity_config::ToolRule {
+            allowed_paths: vec!["/infra/**".to_string()],
+            ..productivity_config::ToolRule::default()
+        };
+        let check = |working_directory: Option<&str>| {
+            RustTool::check_policy(
+                &TerraformPlanTool::new(),
+                &TerraformPlanInput {
+                    working_directory: working_directory.map(ToString::to_string),
+                },
+                &rule,
+            )
+        };
+        assert_eq!(check(Some("/infra/prod")), Ok(()));
+        assert!(check(Some("/infra/../etc")).is_err());
+        // The current directory isn't under /infra
+        assert!(check(None).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Truncation of oversized tool results.
+
+/// Find the largest character boundary at or before `index`.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Find the smallest character boundary at or after `index`.
+fn ceil_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Truncate text to at most roughly `max_bytes` by keeping the head and tail and replacing the middle with a marker.
+///
+/// The marker tells the LLM how much was omitted and includes `hint` to explain how to get the rest.
+pub fn truncate_middle(text: &str, max_bytes: usize, hint: &str) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+
+    let head_end = floor_char_boundary(text, max_bytes / 2);
+    let tail_start = ceil_char_boundary(text, text.len() - max_bytes / 2);
+    let omitted = tail_start - head_end;
+    tracing::info!("Truncating a tool result of {} bytes", text.len());
+    format!(
+        "{}\n[... {omitted} of {} bytes omitted because the result exceeded the {max_bytes} byte limit. {hint} ...]\n{}",
+        &text[..head_end],
+        text.len(),
+        &text[tail_start..],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_unchanged() {
+        assert_eq!(truncate_middle("short", 5, "hint"), "short");
+    }
+
+    #[test]
+    fn keeps_the_head_and_tail() {
+        assert_eq!(
+            truncate_middle("0123456789", 4, "Ask for less."),
+            "01\n[... 6 of 10 bytes omitted because the result exceeded the 4 byte limit. Ask for less. ...]\n89"
+        );
+    }
+
+    #[test]
+    fn cuts_on_char_boundaries() {
+        // Each character is 3 bytes so the limit falls inside characters
+        let text = "一二三四五六";
+        let truncated = truncate_middle(text, 8, "hint");
+        assert_eq!(
+            truncated,
+            "一\n[... 12 of 18 bytes omitted because the result exceeded the 8 byte limit. hint ...]\n六"
+        );
+    }
+
+    #[test]
+    fn uses_per_tool_limits() {
+        let limits = productivity_config::ToolOutputLimits {
+            max_bytes: 100,
+            per_tool_max_bytes: [("http_get_request".to_string(), 4)].into(),
+        };
+        let text = "0123456789";
+        assert_eq!(
+            truncate_middle(text, limits.get_max_bytes("read_files"), "hint"),
+            text
+        );
+        assert!(
+            truncate_middle(text, limits.get_max_bytes("http_get_request"), "hint")
+                .starts_with("01\n[... 6 of 10 bytes omitted")
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use anyhow::Context;
@@ -17,6 +18,21 @@ pub enum LlmProvider {
         url_base: Option<String>,
         /// The model to use.
         model: Option<String>,
+        /// The size of the context window in tokens, Ollama's small default silently truncates long conversations.
+        num_ctx: Option<u32>,
+        /// The sampling temperature.
+        temperature: Option<f32>,
+        /// The random seed, for reproducible responses.
+        seed: Option<i64>,
+        /// How long to keep the model loaded after a request, such as `10m` or `-1m` to keep it loaded indefinitely.
+        keep_alive: Option<String>,
+        /// The format of the response, either `json` or a JSON schema.
+        format: Option<serde_json::Value>,
+        /// Extra HTTP headers to send with every request, for example for an authenticating proxy.
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// A token to send in a bearer `Authorization` header.
+        bearer_token: Option<String>,
     },
 }
 
@@ -40,6 +56,141 @@ impl LlmProvider {
     }
 }
 
+/// Rules restricting the arguments a tool can be invoked with.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ToolRule {
+    /// Glob patterns for the filesystem paths the tool may access, any path is allowed if this is empty.
+    pub allowed_paths: Vec<String>,
+    /// Glob patterns for the filesystem paths the tool may never access.
+    pub denied_paths: Vec<String>,
+    /// Glob patterns for the URLs the tool may request, any URL is allowed if this is empty.
+    pub allowed_urls: Vec<String>,
+    /// The subcommands a binary tool may run, any subcommand is allowed if this is empty.
+    pub allowed_subcommands: Vec<String>,
+}
+
+/// Policy controlling which tools the `ask` tool offers to the LLM.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ToolPolicy {
+    /// The names of the tools to offer, all tools are offered if this is not set.
+    pub enabled_tools: Option<Vec<String>>,
+    /// The names of the tools to never offer.
+    pub disabled_tools: Vec<String>,
+    /// Rules restricting the arguments of tools, keyed by tool name.
+    pub rules: HashMap<String, ToolRule>,
+}
+
+impl ToolPolicy {
+    /// Check whether a tool may be offered to the LLM.
+    #[must_use]
+    pub fn is_tool_enabled(&self, name: &str) -> bool {
+        !self.disabled_tools.iter().any(|tool| tool == name)
+            && self
+                .enabled_tools
+                .as_ref()
+                .is_none_or(|tools| tools.iter().any(|tool| tool == name))
+    }
+}
+
+/// Configuration for redacting secrets from tool output before it is sent to the LLM.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Send tool output to the LLM without redacting secrets.
+    pub disabled: bool,
+    /// Extra regular expressions matching secrets to redact.
+    pub extra_patterns: Vec<String>,
+}
+
+/// Limits on the size of tool results sent to the LLM.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct ToolOutputLimits {
+    /// The maximum number of bytes of a tool result to send to the LLM.
+    ///
+    /// A token is roughly four bytes of English text.
+    pub max_bytes: usize,
+    /// Overrides of `max_bytes` for specific tools, keyed by tool name.
+    pub per_tool_max_bytes: HashMap<String, usize>,
+}
+
+impl Default for ToolOutputLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024,
+            per_tool_max_bytes: HashMap::new(),
+        }
+    }
+}
+
+impl ToolOutputLimits {
+    /// Get the maximum number of bytes of a tool's result to send to the LLM.
+    #[must_use]
+    pub fn get_max_bytes(&self, tool_name: &str) -> usize {
+        self.per_tool_max_bytes
+            .get(tool_name)
+            .copied()
+            .unwrap_or(self.max_bytes)
+    }
+}
+
+/// What a custom tool sends back to the LLM.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomToolOutputMode {
+    /// The return code, stdout, and stderr.
+    #[default]
+    Output,
+    /// Only the return code.
+    ExitCode,
+}
+
+/// A tool which runs a binary, declared in the config instead of compiled into `ask`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct CustomToolConfig {
+    /// The name of the tool. Must match the regex `^[a-zA-Z0-9_-]{1,64}$`.
+    pub name: String,
+    /// What the tool does and when the LLM should use it.
+    pub description: String,
+    /// The binary to run.
+    pub binary: String,
+    /// Arguments which are always passed to the binary before the templated arguments.
+    #[serde(default)]
+    pub argument_prefix: Vec<String>,
+    /// Arguments with `{parameter}` placeholders which are replaced with the tool's input.
+    ///
+    /// Arguments with placeholders for parameters the LLM didn't provide are left out, and an argument which is only
+    /// a placeholder for an array parameter becomes one argument per element.
+    #[serde(default)]
+    pub argument_template: Vec<String>,
+    /// A JSON schema for the object of parameters the LLM provides.
+    #[serde(default)]
+    pub parameters: serde_json::Value,
+    /// What the tool sends back to the LLM.
+    #[serde(default)]
+    pub output_mode: CustomToolOutputMode,
+    /// Whether to show the binary's output to the user.
+    #[serde(default)]
+    pub print_to_console: bool,
+}
+
+/// A Model Context Protocol server which `ask` launches and communicates with over stdio.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct McpServerConfig {
+    /// The name of the server, used to prefix the names of its tools.
+    pub name: String,
+    /// The command which starts the server.
+    pub command: String,
+    /// The arguments to pass to the command.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables to set for the server.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
 /// Configuration for productivity CLI tools.
 #[derive(serde::Deserialize, serde::Serialize, Debug, Default)]
 pub struct Config {
@@ -57,16 +208,42 @@ pub struct Config {
     pub llm_provider: LlmProvider,
     /// Extra system prompt content for the `ask` tool.
     pub ask_system_prompt: Option<String>,
+    /// The syntax highlighting theme for the `ask` tool, either a built in theme name or the path to a `.tmTheme` file.
+    #[serde(default)]
+    pub ask_theme: Option<String>,
+    /// The policy controlling which tools the `ask` tool can use.
+    #[serde(default)]
+    pub tool_policy: ToolPolicy,
+    /// Secret redaction settings for the `ask` tool.
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    /// Size limits for tool results in the `ask` tool.
+    #[serde(default)]
+    pub tool_output_limits: ToolOutputLimits,
+    /// Tools which run binaries, offered to the LLM by the `ask` tool alongside its built in tools.
+    #[serde(default)]
+    pub custom_tools: Vec<CustomToolConfig>,
+    /// Model Context Protocol servers whose tools are offered to the LLM by the `ask` tool.
+    #[serde(default)]
+    pub mcp_servers: Vec<McpServerConfig>,
 }
 
 impl Config {
+    /// Get the project directories the config and cache are stored in.
+    fn get_project_dirs() -> anyhow::Result<directories::ProjectDirs> {
+        directories::ProjectDirs::from("bot", "bucket", "productivity")
+            .context("Could not find project directories")
+    }
+
+    /// Get the path of the config file, which might not exist yet.
+    pub fn get_config_file_path() -> anyhow::Result<PathBuf> {
+        Ok(Self::get_project_dirs()?.config_dir().join("config.json"))
+    }
+
     /// Get the config using the XDG directories structure.
     pub fn get_or_default() -> anyhow::Result<Self> {
-        let Some(project_dirs) = directories::ProjectDirs::from("bot", "bucket", "productivity")
-        else {
-            anyhow::bail!("Could not find project directories");
-        };
-        let config_file = project_dirs.config_dir().join("config.json");
+        let project_dirs = Self::get_project_dirs()?;
+        let config_file = Self::get_config_file_path()?;
         let mut config = if config_file.exists() {
             Self::load(config_file).context("Loading config")?
         } else {